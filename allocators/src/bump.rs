@@ -0,0 +1,214 @@
+use core::{
+    alloc::Layout,
+    cell::Cell,
+    marker::PhantomData,
+    ptr::{
+        self,
+        null_mut,
+        NonNull,
+    },
+};
+
+use crate::{
+    align_up,
+    HeapAllocator,
+    stats::{
+        HeapStatistics,
+        HeapStats,
+        Usage,
+    },
+};
+
+/// Hands out memory by moving a pointer forward.
+///
+/// Freeing the most recent allocation moves the pointer back, anything else is only
+/// reclaimed once all allocations are freed.
+pub struct BumpAllocator {
+    heap_start: usize,
+    heap_end: usize,
+    next: usize,
+    allocations: usize,
+    usage: Usage,
+}
+
+impl BumpAllocator {
+    /// Creates a new empty bump allocator
+    pub const fn new() -> Self {
+        BumpAllocator {
+            heap_start: 0,
+            heap_end: 0,
+            next: 0,
+            allocations: 0,
+            usage: Usage::new(),
+         }
+    }
+
+    /// Initializes the bump allocator with the given heap bounds.
+    ///
+    /// # Safety
+    /// This method is unsafe because the caller must ensure that the given
+    /// memory range is unused. Also, this method must be called only once.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.heap_start = heap_start;
+        self.heap_end = heap_start + heap_size;
+        self.next = heap_start;
+    }
+}
+
+impl Default for BumpAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeapAllocator for BumpAllocator {
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let alloc_start = align_up(self.next, layout.align());
+        let alloc_end = match alloc_start.checked_add(layout.size()) {
+            Some(end) => end,
+            None => return null_mut(),
+        };
+        if alloc_end > self.heap_end {
+            // out of memory
+            return null_mut();
+        }
+        self.next = alloc_end;
+        self.allocations += 1;
+        self.usage.alloc(layout.size());
+        alloc_start as *mut u8
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        self.usage.dealloc(layout.size());
+
+        self.allocations -= 1;
+        if self.allocations == 0 {
+            self.next = self.heap_start;
+        } else if ptr as usize + layout.size() == self.next {
+            // the most recent allocation, freeing in reverse order works like a stack
+            self.next = ptr as usize;
+        }
+    }
+
+    fn top(&self) -> usize {
+        self.heap_end
+    }
+
+    unsafe fn extend(&mut self, size: usize) {
+        self.heap_end += size;
+    }
+}
+
+impl HeapStatistics for BumpAllocator {
+    fn stats(&self) -> HeapStats {
+        // everything behind 'next' is one free region
+        let free = self.heap_end - self.next;
+        self.usage.stats(self.heap_end - self.heap_start, free, Some(free))
+    }
+}
+
+/// A bump allocator over memory owned by the caller, for short lived allocations
+/// that should not go through the global heap, e.g. the scratch memory of a request.
+///
+/// Values are never dropped, the memory is handed out again after a reset.
+/// Resets need '&mut self', so nothing allocated before can still be borrowed.
+pub struct BumpArena<'a> {
+    start: usize,
+    end: usize,
+    next: Cell<usize>,
+    _memory: PhantomData<&'a mut [u8]>,
+}
+
+/// A position in a BumpArena that can be reset to, see `BumpArena::checkpoint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
+impl<'a> BumpArena<'a> {
+    /// Creates an arena handing out the given memory.
+    pub fn new(memory: &'a mut [u8]) -> Self {
+        let start = memory.as_mut_ptr() as usize;
+        BumpArena {
+            start,
+            end: start + memory.len(),
+            next: Cell::new(start),
+            _memory: PhantomData,
+        }
+    }
+
+    /// Returns memory for 'layout', or None if the arena is full.
+    pub fn alloc_layout(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let alloc_start = align_up(self.next.get(), layout.align());
+        let alloc_end = alloc_start.checked_add(layout.size())?;
+        if alloc_end > self.end {
+            return None;
+        }
+        self.next.set(alloc_end);
+        NonNull::new(alloc_start as *mut u8)
+    }
+
+    /// Moves 'value' into the arena, or returns None if the arena is full.
+    // every call hands out different memory, so the mutable references never alias
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc<T>(&self, value: T) -> Option<&mut T> {
+        let ptr = self.alloc_layout(Layout::new::<T>())?.as_ptr() as *mut T;
+        unsafe {
+            ptr.write(value);
+            Some(&mut *ptr)
+        }
+    }
+
+    /// Copies 'values' into the arena, or returns None if the arena is full.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice_copy<T: Copy>(&self, values: &[T]) -> Option<&mut [T]> {
+        let layout = Layout::array::<T>(values.len()).ok()?;
+        let ptr = self.alloc_layout(layout)?.as_ptr() as *mut T;
+        unsafe {
+            ptr::copy_nonoverlapping(values.as_ptr(), ptr, values.len());
+            Some(core::slice::from_raw_parts_mut(ptr, values.len()))
+        }
+    }
+
+    /// Remembers the current position, everything allocated after it is freed
+    /// by `reset_to`.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.next.get())
+    }
+
+    /// Frees everything allocated since 'checkpoint' was taken.
+    /// Panics if the checkpoint was not taken from this arena, or lies past an
+    /// earlier reset.
+    pub fn reset_to(&mut self, checkpoint: Checkpoint) {
+        assert!(
+            checkpoint.0 >= self.start && checkpoint.0 <= self.next.get(),
+            "checkpoint does not belong to this arena"
+        );
+        self.next.set(checkpoint.0);
+    }
+
+    /// Frees everything in the arena.
+    pub fn reset(&mut self) {
+        self.next.set(self.start);
+    }
+
+    /// Runs 'f' with the arena and frees everything 'f' allocated afterwards.
+    pub fn scope<R>(&mut self, f: impl FnOnce(&BumpArena<'a>) -> R) -> R {
+        let checkpoint = self.checkpoint();
+        let result = f(self);
+        self.reset_to(checkpoint);
+        result
+    }
+
+    /// Returns the number of bytes handed out, including alignment padding.
+    pub fn used(&self) -> usize {
+        self.next.get() - self.start
+    }
+
+    /// Returns the number of bytes left.
+    pub fn remaining(&self) -> usize {
+        self.end - self.next.get()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.end - self.start
+    }
+}
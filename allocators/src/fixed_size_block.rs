@@ -0,0 +1,286 @@
+use core::{
+    alloc::Layout,
+    mem,
+    ptr::{
+        null_mut,
+        NonNull,
+    },
+};
+
+use crate::{
+    align_up,
+    HeapAllocator,
+    stats::{
+        HeapStatistics,
+        HeapStats,
+        Usage,
+    },
+};
+
+struct ListNode {
+    // all nodes have the same fixed size
+    next: Option<&'static mut ListNode>
+}
+
+/// A slab is a chunk of memory from the fallback allocator, aligned to its own size
+/// and carved up into blocks of a single size. Its header sits in front of the first block.
+struct Slab {
+    // free blocks of this slab
+    free_list: Option<&'static mut ListNode>,
+    free_count: usize,
+    // next slab of the same size that still has free blocks
+    next: Option<&'static mut Slab>,
+}
+
+/// Memory taken by a slab of the given block size class.
+/// At least a page, and large enough for 8 blocks.
+fn slab_size(index: usize) -> usize {
+    (BLOCK_SIZES[index] * 8).max(SLAB_SIZE)
+}
+
+/// Offset of the first block in a slab, behind the header.
+fn first_block_offset(index: usize) -> usize {
+    align_up(mem::size_of::<Slab>(), BLOCK_SIZES[index])
+}
+
+/// Number of blocks a slab of the given block size class holds.
+fn slab_capacity(index: usize) -> usize {
+    (slab_size(index) - first_block_offset(index)) / BLOCK_SIZES[index]
+}
+
+fn slab_layout(index: usize) -> Layout {
+    Layout::from_size_align(slab_size(index), slab_size(index)).unwrap()
+}
+
+/// block sizes
+/// must be a power of 2 to help with alignments (size alignments must be powers of 2)
+pub const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128,256, 512, 1024, 2048];
+
+/// minimum size of a slab
+const SLAB_SIZE: usize = 4096;
+
+/// FSB -> [F]ixed[S]ized[B]lock 
+///
+/// Blocks are carved from slabs, one list of slabs with free blocks per block size.
+/// Each size keeps one slab whose blocks are all free, further empty slabs are given
+/// back to the fallback allocator, so memory can move between block sizes without
+/// a slab being created and released on every allocation.
+pub struct FSBAllocator {
+    slab_heads: [Option<&'static mut Slab>; BLOCK_SIZES.len()],
+    fallback_allocator: linked_list_allocator::Heap,
+    // number of free blocks in the slabs of each size
+    free_blocks: [usize; BLOCK_SIZES.len()],
+    // number of blocks of each size handed out
+    blocks_in_use: [usize; BLOCK_SIZES.len()],
+    // number of slabs of each size
+    slabs: [usize; BLOCK_SIZES.len()],
+    // number of slabs of each size whose blocks are all free
+    empty_slabs: [usize; BLOCK_SIZES.len()],
+    usage: Usage,
+}
+
+impl FSBAllocator {
+    pub const fn new() -> Self {
+        const EMPTY: Option<&'static mut Slab> = None;
+        FSBAllocator { 
+            slab_heads: [ EMPTY; BLOCK_SIZES.len() ], 
+            fallback_allocator: linked_list_allocator::Heap::empty(),
+            free_blocks: [0; BLOCK_SIZES.len()],
+            blocks_in_use: [0; BLOCK_SIZES.len()],
+            slabs: [0; BLOCK_SIZES.len()],
+            empty_slabs: [0; BLOCK_SIZES.len()],
+            usage: Usage::new(),
+        }
+    }
+
+    /// Initializes the allocator with the given heap bounds.
+    ///
+    /// # Safety
+    /// This method is unsafe because the caller must ensure that the given
+    /// memory range is unused. Also, this method must be called only once.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.fallback_allocator.init(heap_start, heap_size);
+    } 
+
+    /// Hands out a block of the given size class, adding a new slab if none has free blocks.
+    fn alloc_block(&mut self, index: usize) -> *mut u8 {
+        if self.slab_heads[index].is_none() && !self.add_slab(index) {
+            return null_mut();
+        }
+        let slab = self.slab_heads[index].as_mut().unwrap();
+        if slab.free_count == slab_capacity(index) {
+            self.empty_slabs[index] -= 1;
+        }
+        let block = slab.free_list.take().unwrap();
+        slab.free_list = block.next.take();
+        slab.free_count -= 1;
+        if slab.free_count == 0 {
+            // slab is full, it leaves the list
+            let slab = self.slab_heads[index].take().unwrap();
+            self.slab_heads[index] = slab.next.take();
+        }
+        self.free_blocks[index] -= 1;
+        self.blocks_in_use[index] += 1;
+        block as *mut ListNode as *mut u8
+    }
+
+    /// Returns a block to its slab, giving the slab back to the fallback allocator once it is empty
+    /// and its size class already has an empty slab.
+    unsafe fn dealloc_block(&mut self, ptr: *mut u8, index: usize) {
+        // Verifying that the Block is capable of storing a node
+        assert!(mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
+        assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
+        // slabs are aligned to their size, so the header is found by rounding down
+        let slab_ptr = (ptr as usize & !(slab_size(index) - 1)) as *mut Slab;
+        let slab = &mut *slab_ptr;
+
+        let new_node_ptr = ptr as *mut ListNode;
+        new_node_ptr.write(ListNode {
+            next: slab.free_list.take(),
+        });
+        slab.free_list = Some(&mut *new_node_ptr);
+        slab.free_count += 1;
+        self.free_blocks[index] += 1;
+        self.blocks_in_use[index] -= 1;
+
+        if slab.free_count == 1 {
+            // slab was full, it has a free block again
+            slab.next = self.slab_heads[index].take();
+            self.slab_heads[index] = Some(slab);
+        }
+        if (*slab_ptr).free_count == slab_capacity(index) {
+            if self.empty_slabs[index] > 0 {
+                self.remove_slab(slab_ptr, index);
+            } else {
+                self.empty_slabs[index] += 1;
+            }
+        }
+    }
+
+    /// Gets a new slab for the given size class from the fallback allocator
+    /// and puts it in front of the slab list.
+    fn add_slab(&mut self, index: usize) -> bool {
+        let slab_start = self.fallback_alloc(slab_layout(index));
+        if slab_start.is_null() {
+            return false;
+        }
+        // chain all blocks of the slab into its free list, starting from the back
+        let mut free_list = None;
+        for i in (0..slab_capacity(index)).rev() {
+            let offset = first_block_offset(index) + i * BLOCK_SIZES[index];
+            let node_ptr = unsafe { slab_start.add(offset) } as *mut ListNode;
+            unsafe {
+                node_ptr.write(ListNode { next: free_list });
+                free_list = Some(&mut *node_ptr);
+            }
+        }
+        let slab_ptr = slab_start as *mut Slab;
+        unsafe {
+            slab_ptr.write(Slab {
+                free_list,
+                free_count: slab_capacity(index),
+                next: self.slab_heads[index].take(),
+            });
+            self.slab_heads[index] = Some(&mut *slab_ptr);
+        }
+        self.free_blocks[index] += slab_capacity(index);
+        self.slabs[index] += 1;
+        self.empty_slabs[index] += 1;
+        true
+    }
+
+    /// Unlinks an empty slab from its list and gives it back to the fallback allocator.
+    unsafe fn remove_slab(&mut self, slab_ptr: *mut Slab, index: usize) {
+        let mut current = &mut self.slab_heads[index];
+        while current.as_ref().is_some_and(|slab| !core::ptr::eq(*slab, slab_ptr)) {
+            current = &mut current.as_mut().unwrap().next;
+        }
+        let slab = current.take().expect("empty slab not in slab list");
+        *current = slab.next.take();
+
+        self.free_blocks[index] -= slab_capacity(index);
+        self.slabs[index] -= 1;
+        self.fallback_allocator.deallocate(NonNull::new_unchecked(slab_ptr as *mut u8), slab_layout(index));
+    }
+
+    fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
+        match self.fallback_allocator.allocate_first_fit(layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => null_mut(),
+        }
+    }
+
+}
+
+impl Default for FSBAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeapAllocator for FSBAllocator {
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let ptr = match best_fit_index(&layout) {
+            Some(index) => self.alloc_block(index),
+            None => self.fallback_alloc(layout),
+        };
+        if !ptr.is_null() {
+            self.usage.alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        match best_fit_index(&layout) {
+            Some(index) => self.dealloc_block(ptr, index),
+            None => {
+                let ptr = NonNull::new(ptr).unwrap();
+                self.fallback_allocator.deallocate(ptr, layout);
+            }
+        }
+        self.usage.dealloc(layout.size());
+    }
+
+    fn top(&self) -> usize {
+        self.fallback_allocator.top()
+    }
+
+    unsafe fn extend(&mut self, size: usize) {
+        self.fallback_allocator.extend(size);
+    }
+
+    fn min_growth(&self, layout: &Layout) -> usize {
+        // small allocations need a whole slab, which is aligned to its size
+        match best_fit_index(layout) {
+            Some(index) => 2 * slab_size(index),
+            None => layout.size() + layout.align(),
+        }
+    }
+}
+
+impl HeapStatistics for FSBAllocator {
+    fn stats(&self) -> HeapStats {
+        let free_block_bytes: usize = self.free_blocks.iter()
+            .zip(BLOCK_SIZES)
+            .map(|(count, size)| count * size)
+            .sum();
+        let free_bytes = self.fallback_allocator.free() + free_block_bytes;
+        // the fallback allocator doesn't expose its holes, so the largest one is unknown
+        let mut stats = self.usage.stats(self.fallback_allocator.size(), free_bytes, None);
+        for (index, class) in stats.block_classes.iter_mut().enumerate() {
+            class.free_blocks = self.free_blocks[index];
+            class.blocks_in_use = self.blocks_in_use[index];
+            class.slabs = self.slabs[index];
+        }
+        stats
+    }
+}
+
+// Choose the best fitting block size for a given layout
+// Returns an index into the BLOCK_SIZES array
+// which is used as an index into the slab_heads array
+fn best_fit_index(layout: &Layout) -> Option<usize> {
+    let required_block_size = layout.size().max(layout.align());
+    // Returns an Option of the index 
+    BLOCK_SIZES.iter().position( |&s| s >= required_block_size )
+}
\ No newline at end of file
@@ -0,0 +1,53 @@
+//! The heap allocator algorithms of oubre_os.
+//!
+//! The allocators only manage the memory range they are given and know nothing
+//! about paging, so they run just as well on a byte array on the host.
+//! Growing the heap when an allocator is full is left to the kernel.
+#![no_std]
+
+pub mod bump;
+pub mod linked_list;
+pub mod fixed_size_block;
+pub mod stats;
+
+use core::alloc::Layout;
+
+/// The interface the kernel's global allocator drives the allocators through.
+pub trait HeapAllocator {
+    /// Hands out memory for 'layout', returns a null pointer if the heap is full.
+    fn allocate(&mut self, layout: Layout) -> *mut u8;
+
+    /// Gives back memory handed out by 'allocate'.
+    ///
+    /// # Safety
+    /// This method is unsafe because 'ptr' must have been returned by 'allocate'
+    /// of this allocator for the same layout, and must not be used afterwards.
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout);
+
+    /// Returns the end address of the heap.
+    fn top(&self) -> usize;
+
+    /// Adds 'size' bytes at the end of the heap.
+    ///
+    /// # Safety
+    /// This method is unsafe because the caller must ensure that the memory
+    /// directly behind 'top' is valid and unused.
+    unsafe fn extend(&mut self, size: usize);
+
+    /// Returns how much the heap has to grow for an allocation of 'layout' to
+    /// succeed after 'allocate' failed.
+    fn min_growth(&self, layout: &Layout) -> usize {
+        layout.size() + layout.align()
+    }
+}
+
+/// Align the given address 'addr' upwards to alignment 'align'.
+pub fn align_up(addr: usize, align: usize) -> usize {
+    // ( addr + align - 1) & !(align -1)
+    let remainder = addr % align;
+    if remainder == 0 {
+        addr // addr already aligned
+    } else {
+        addr - remainder + align
+    }
+}
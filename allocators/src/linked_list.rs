@@ -0,0 +1,246 @@
+use crate::{
+    align_up,
+    HeapAllocator,
+    stats::{
+        HeapStatistics,
+        HeapStats,
+        Usage,
+    },
+};
+
+use core::{
+    alloc::Layout,
+    ptr::null_mut,
+    mem,
+};
+
+
+struct ListNode {
+    size: usize,
+    next: Option<&'static mut ListNode>,
+}
+
+impl ListNode {
+    const fn new(size: usize) -> Self {
+        ListNode { size, next: None }
+    }
+
+    fn start_addr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn end_addr(&self) -> usize {
+        self.start_addr() + self.size
+    }
+}
+
+pub struct LinkedListAllocator {
+    head: ListNode,
+    heap_start: usize,
+    heap_end: usize,
+    usage: Usage,
+}
+
+impl LinkedListAllocator {
+    //// Creates an empty LinkedListAllocator
+   pub const fn new() -> Self {
+        LinkedListAllocator { 
+            head: ListNode::new(0),
+            heap_start: 0,
+            heap_end: 0,
+            usage: Usage::new(),
+        }
+    }
+    /// Initialize the allocator with the given heap bounds.
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must guarantee that the given
+    /// heap bounds are valid and that the heap is unused. This method must be
+    /// called only once.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.add_free_region(heap_start, heap_size);
+        self.heap_start = heap_start;
+        self.heap_end = heap_start + heap_size;
+    }
+
+    /// Adds the given memory region to the list, which is kept sorted by address.
+    /// The region is merged with its neighbours if they are adjacent to it.
+    unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
+
+        // The freed region should be capable of holding a ListNode
+        assert_eq!(align_up(addr, mem::align_of::<ListNode>()), addr);
+        assert!(size >= mem::size_of::<ListNode>());
+
+        // find the last region that starts before the new one
+        // the head is not part of the heap, so it is never merged with
+        let mut current_node = &mut self.head;
+        let mut is_head = true;
+        while current_node.next.as_ref().is_some_and(|next| next.start_addr() < addr) {
+            current_node = current_node.next.as_mut().unwrap();
+            is_head = false;
+        }
+
+        // overlapping regions mean a double free or a corrupted list
+        assert!(is_head || current_node.end_addr() <= addr, "freed region overlaps a free region");
+        let mut size = size;
+        let merge_next = match current_node.next {
+            Some(ref next) => {
+                assert!(addr + size <= next.start_addr(), "freed region overlaps a free region");
+                addr + size == next.start_addr()
+            }
+            None => false,
+        };
+        if merge_next {
+            // swallow the following region
+            let next = current_node.next.take().unwrap();
+            size += next.size;
+            current_node.next = next.next.take();
+        }
+        if !is_head && current_node.end_addr() == addr {
+            // the previous region simply gets bigger
+            current_node.size += size;
+            return;
+        }
+
+        // create and insert a ListNode after the previous region
+        let mut node = ListNode::new(size);
+        node.next = current_node.next.take();
+        let node_ptr = addr as *mut ListNode;
+        node_ptr.write(node);
+        current_node.next = Some(&mut *node_ptr)
+    }
+
+    /// Looks for a free mem region with the given size and alignment and removes it from the list
+    /// Returns the list node and the start address of the allocated mem
+    fn find_region(&mut self, size: usize, align: usize) 
+    -> Option<(&'static mut ListNode, usize)>
+    {
+        // reference to current list node, updated on each iteration
+        let mut current_node = &mut self.head;
+        // searching for large suitable mem region in the linkedlist
+        while let Some(ref mut region) = current_node.next {
+            if let Ok(alloc_start) = Self::alloc_from_region(region, size, align) {
+                // suitable region for removal
+                let next = region.next.take();
+                let ret = Some((current_node.next.take().unwrap(), alloc_start));
+                current_node.next = next;
+                return ret;
+            } else {
+                current_node = current_node.next.as_mut().unwrap();
+            }
+        }
+        None
+    }
+
+    fn alloc_from_region(region: &ListNode, size: usize, align: usize) 
+    -> Result<usize, ()>
+    {
+        let alloc_start = align_up(region.start_addr(), align);
+        let alloc_end = alloc_start.checked_add(size).ok_or(())?;
+
+        let skipped_size = alloc_start - region.start_addr();
+        if skipped_size > 0 && skipped_size < mem::size_of::<ListNode>() {
+            // space skipped for alignment too small to hold ListNode.
+            return Err(());
+        }
+
+        if alloc_end > region.end_addr() {
+            // region too small -> memory overflow
+            return Err(());
+        }
+
+        let excess_size = region.end_addr() - alloc_end;
+        if excess_size > 0 && excess_size < mem::size_of::<ListNode>() {
+            // rest of region too small to hold ListNode.
+            return Err(());
+        }
+
+        // region suitable for allocation
+        Ok(alloc_start)
+    }
+
+    /// Adjust the layout to ensure the the allocated memory is large enough to store a ListNode
+    /// Returns a tuple of the size and alignment
+    fn size_align(layout: Layout) -> (usize, usize) {
+        let layout = layout.align_to(mem::size_of::<ListNode>())
+            .expect("alignment adjustment failed")
+            .pad_to_align();
+        let size = layout.size().max(mem::size_of::<ListNode>());
+        (size, layout.align())
+    }
+}
+
+impl Default for LinkedListAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeapAllocator for LinkedListAllocator {
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        // layout adjustments to ensure that the allocated region can store a ListNode
+        let (size, align) = Self::size_align(layout);
+
+        if let Some((region, alloc_start)) = self.find_region(size, align) {
+            let (region_start, region_end) = (region.start_addr(), region.end_addr());
+            let alloc_end = alloc_start.checked_add(size).expect("overflow");
+            let excess_size = region_end - alloc_end;
+            unsafe {
+                if excess_size > 0 {
+                    self.add_free_region(alloc_end, excess_size);
+                }
+                // give back the part of the region skipped for alignment
+                if alloc_start > region_start {
+                    self.add_free_region(region_start, alloc_start - region_start);
+                }
+            }
+            self.usage.alloc(layout.size());
+            alloc_start as *mut u8
+        } else {
+            null_mut()
+        }
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        // layout adjustments to ensure that the allocated region can store a ListNode
+        let (size, _) = Self::size_align(layout);
+
+        self.add_free_region(ptr as usize, size);
+        self.usage.dealloc(layout.size());
+    }
+
+    fn top(&self) -> usize {
+        self.heap_end
+    }
+
+    unsafe fn extend(&mut self, size: usize) {
+        self.add_free_region(self.heap_end, size);
+        self.heap_end += size;
+    }
+
+    fn min_growth(&self, layout: &Layout) -> usize {
+        let (size, align) = Self::size_align(*layout);
+        size + align
+    }
+}
+
+impl HeapStatistics for LinkedListAllocator {
+    fn stats(&self) -> HeapStats {
+        let (mut free_bytes, mut largest) = (0, 0);
+        let mut current_node = &self.head;
+        while let Some(ref region) = current_node.next {
+            free_bytes += region.size;
+            largest = largest.max(region.size);
+            current_node = region;
+        }
+        self.usage.stats(self.heap_end - self.heap_start, free_bytes, Some(largest))
+    }
+
+    fn for_each_free_region(&self, f: &mut dyn FnMut(usize, usize)) {
+        let mut current_node = &self.head;
+        while let Some(ref region) = current_node.next {
+            f(region.start_addr(), region.size);
+            current_node = region;
+        }
+    }
+}
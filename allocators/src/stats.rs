@@ -0,0 +1,114 @@
+use crate::fixed_size_block::BLOCK_SIZES;
+
+/// Usage of one block size of the FSBAllocator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockClassStats {
+    pub block_size: usize,
+    /// free blocks left in the slabs of this size
+    pub free_blocks: usize,
+    /// blocks of this size currently handed out
+    pub blocks_in_use: usize,
+    /// slabs the blocks of this size are carved from
+    pub slabs: usize,
+}
+
+/// A snapshot of how an allocator's heap is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    /// size of the memory managed by the allocator
+    pub heap_size: usize,
+    /// bytes requested by live allocations
+    pub bytes_in_use: usize,
+    /// highest value bytes_in_use ever reached, None if the allocator can't tell
+    pub peak_bytes_in_use: Option<usize>,
+    /// bytes that can still be handed out
+    pub free_bytes: usize,
+    /// size of the largest free region, None if the allocator can't tell
+    pub largest_free_region: Option<usize>,
+    /// number of allocations made so far
+    pub allocations: usize,
+    /// number of deallocations made so far
+    pub deallocations: usize,
+    /// per block size counts, only filled in by the FSBAllocator
+    pub block_classes: [BlockClassStats; BLOCK_SIZES.len()],
+}
+
+impl HeapStats {
+    /// Returns the number of allocations that have not been freed yet.
+    pub fn live_allocations(&self) -> usize {
+        self.allocations - self.deallocations
+    }
+}
+
+/// Allocators that can report how their heap is used.
+pub trait HeapStatistics {
+    fn stats(&self) -> HeapStats;
+
+    /// Calls 'f' with the start address and size of every free region,
+    /// for allocators that keep a list of them.
+    fn for_each_free_region(&self, _f: &mut dyn FnMut(usize, usize)) {}
+}
+
+// the external linked_list_allocator only knows how much of its heap is used,
+// not how much was used at most
+impl HeapStatistics for linked_list_allocator::Heap {
+    fn stats(&self) -> HeapStats {
+        let mut stats = Usage::new().stats(self.size(), self.free(), None);
+        stats.bytes_in_use = self.used();
+        stats.peak_bytes_in_use = None;
+        stats
+    }
+}
+
+/// Usage counters every allocator keeps up to date on alloc and dealloc.
+pub(crate) struct Usage {
+    bytes_in_use: usize,
+    peak_bytes_in_use: usize,
+    allocations: usize,
+    deallocations: usize,
+}
+
+impl Usage {
+    pub const fn new() -> Self {
+        Usage {
+            bytes_in_use: 0,
+            peak_bytes_in_use: 0,
+            allocations: 0,
+            deallocations: 0,
+        }
+    }
+
+    pub fn alloc(&mut self, size: usize) {
+        self.bytes_in_use += size;
+        self.peak_bytes_in_use = self.peak_bytes_in_use.max(self.bytes_in_use);
+        self.allocations += 1;
+    }
+
+    pub fn dealloc(&mut self, size: usize) {
+        self.bytes_in_use -= size;
+        self.deallocations += 1;
+    }
+
+    /// Combines the counters with the allocator specific numbers into HeapStats.
+    pub fn stats(&self, heap_size: usize, free_bytes: usize, largest_free_region: Option<usize>) -> HeapStats {
+        let mut block_classes = [BlockClassStats {
+            block_size: 0,
+            free_blocks: 0,
+            blocks_in_use: 0,
+            slabs: 0,
+        }; BLOCK_SIZES.len()];
+        for (class, &block_size) in block_classes.iter_mut().zip(BLOCK_SIZES) {
+            class.block_size = block_size;
+        }
+        HeapStats {
+            heap_size,
+            bytes_in_use: self.bytes_in_use,
+            peak_bytes_in_use: Some(self.peak_bytes_in_use),
+            free_bytes,
+            largest_free_region,
+            allocations: self.allocations,
+            deallocations: self.deallocations,
+            block_classes,
+        }
+    }
+}
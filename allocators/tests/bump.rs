@@ -0,0 +1,128 @@
+mod common;
+
+use std::alloc::Layout;
+
+use common::{
+    Arena,
+    Checker,
+    Rng,
+    ARENA_SIZE,
+};
+use oubre_allocators::{
+    bump::{
+        BumpAllocator,
+        BumpArena,
+    },
+    stats::HeapStatistics,
+    HeapAllocator,
+};
+
+fn allocator(arena: &Arena, size: usize) -> BumpAllocator {
+    let mut allocator = BumpAllocator::new();
+    unsafe { allocator.init(arena.start(), size) };
+    allocator
+}
+
+#[test]
+fn random_allocations_are_recovered() {
+    for seed in 1..=20 {
+        let arena = Arena::new(ARENA_SIZE);
+        let mut allocator = allocator(&arena, ARENA_SIZE);
+        let mut checker = Checker::new(&arena);
+        checker.run(&mut allocator, &mut Rng::new(seed), 2000);
+        checker.free_all(&mut allocator);
+
+        // the whole heap is free again once the last allocation is gone
+        let stats = allocator.stats();
+        assert_eq!(stats.free_bytes, ARENA_SIZE, "seed {}", seed);
+        assert_eq!((stats.bytes_in_use, stats.live_allocations()), (0, 0));
+        assert!(checker.alloc(&mut allocator, Layout::from_size_align(ARENA_SIZE, 8).unwrap()));
+    }
+}
+
+#[test]
+fn extend_makes_room() {
+    let arena = Arena::new(2 * 4096);
+    let mut allocator = allocator(&arena, 4096);
+    let mut checker = Checker::new(&arena);
+    assert!(checker.alloc(&mut allocator, Layout::from_size_align(4000, 8).unwrap()));
+    let layout = Layout::from_size_align(1000, 8).unwrap();
+    assert!(!checker.alloc(&mut allocator, layout));
+
+    unsafe { allocator.extend(4096) };
+    assert!(checker.alloc(&mut allocator, layout));
+    checker.free_all(&mut allocator);
+    assert_eq!(allocator.stats().free_bytes, 2 * 4096);
+}
+
+#[test]
+fn freeing_the_latest_allocation_reclaims_it() {
+    let arena = Arena::new(4096);
+    let mut allocator = allocator(&arena, 4096);
+    let layout = Layout::from_size_align(1000, 8).unwrap();
+    let first = allocator.allocate(layout);
+    let second = allocator.allocate(layout);
+    let free = allocator.stats().free_bytes;
+
+    // freed in reverse order, every free moves the pointer back
+    let third = allocator.allocate(layout);
+    unsafe { allocator.deallocate(third, layout) };
+    assert_eq!(allocator.stats().free_bytes, free);
+    unsafe { allocator.deallocate(second, layout) };
+    assert_eq!(allocator.stats().free_bytes, free + 1000);
+    assert_eq!(allocator.allocate(layout), second);
+
+    // anything below the top stays taken until the top is gone
+    unsafe { allocator.deallocate(first, layout) };
+    assert_eq!(allocator.stats().free_bytes, free);
+    unsafe { allocator.deallocate(second, layout) };
+    assert_eq!(allocator.stats().free_bytes, 4096);
+}
+
+#[test]
+fn arena_hands_out_aligned_memory() {
+    let mut memory = [0u8; 256];
+    let arena = BumpArena::new(&mut memory);
+    let byte = arena.alloc(1u8).unwrap();
+    let word = arena.alloc(2u64).unwrap();
+    assert_eq!(word as *mut u64 as usize % 8, 0);
+    assert_eq!((*byte, *word), (1, 2));
+    let slice = arena.alloc_slice_copy(&[3u32; 4]).unwrap();
+    assert_eq!(slice, &[3; 4]);
+    assert_eq!(arena.used() + arena.remaining(), arena.capacity());
+
+    // full arenas return None
+    assert!(arena.alloc([0u8; 256]).is_none());
+}
+
+#[test]
+fn arena_resets_to_checkpoints() {
+    let mut memory = [0u8; 256];
+    let mut arena = BumpArena::new(&mut memory);
+    arena.alloc(1u64).unwrap();
+    let checkpoint = arena.checkpoint();
+    let used = arena.used();
+    arena.alloc([0u8; 100]).unwrap();
+    arena.reset_to(checkpoint);
+    assert_eq!(arena.used(), used);
+
+    let sum = arena.scope(|arena| {
+        let values = arena.alloc_slice_copy(&[1u64, 2, 3]).unwrap();
+        values.iter().sum::<u64>()
+    });
+    assert_eq!((sum, arena.used()), (6, used));
+
+    arena.reset();
+    assert_eq!(arena.used(), 0);
+}
+
+#[test]
+#[should_panic(expected = "checkpoint does not belong to this arena")]
+fn arena_rejects_stale_checkpoints() {
+    let mut memory = [0u8; 256];
+    let mut arena = BumpArena::new(&mut memory);
+    arena.alloc(1u64).unwrap();
+    let checkpoint = arena.checkpoint();
+    arena.reset();
+    arena.reset_to(checkpoint);
+}
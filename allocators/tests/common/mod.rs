@@ -0,0 +1,161 @@
+// helpers shared by the allocator tests
+#![allow(dead_code)]
+
+use std::{
+    alloc::{
+        alloc_zeroed,
+        dealloc,
+        Layout,
+    },
+    collections::BTreeMap,
+};
+
+use oubre_allocators::HeapAllocator;
+
+pub const ARENA_SIZE: usize = 1024 * 1024; // 1MiB
+
+/// The memory an allocator under test works on, aligned like the kernel heap.
+pub struct Arena {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl Arena {
+    pub fn new(size: usize) -> Self {
+        let layout = Layout::from_size_align(size, 4096).unwrap();
+        let ptr = unsafe { alloc_zeroed(layout) };
+        assert!(!ptr.is_null());
+        Arena { ptr, layout }
+    }
+
+    pub fn start(&self) -> usize {
+        self.ptr as usize
+    }
+
+    pub fn size(&self) -> usize {
+        self.layout.size()
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr, self.layout) };
+    }
+}
+
+/// A xorshift generator, so every run sees the same sequence for a seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in 0..n
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// Mostly small layouts with the odd large or strongly aligned one, like a kernel heap sees.
+    pub fn layout(&mut self) -> Layout {
+        let size = match self.below(10) {
+            0 => 1 + self.below(16 * 1024),
+            1..=3 => 1 + self.below(2048),
+            _ => 1 + self.below(128),
+        };
+        let align = match self.below(10) {
+            0 => 1 << self.below(13),
+            _ => 1 << self.below(4),
+        };
+        Layout::from_size_align(size, align).unwrap()
+    }
+}
+
+/// Records the live allocations of an allocator and checks every answer it gives.
+///
+/// Each allocation is filled with its own byte, which is verified when it is freed,
+/// so allocations that overlap or get overwritten by the allocator are caught.
+pub struct Checker {
+    heap_start: usize,
+    heap_end: usize,
+    // start address -> layout and fill byte
+    live: BTreeMap<usize, (Layout, u8)>,
+    fill: u8,
+}
+
+impl Checker {
+    pub fn new(arena: &Arena) -> Self {
+        Checker {
+            heap_start: arena.start(),
+            heap_end: arena.start() + arena.size(),
+            live: BTreeMap::new(),
+            fill: 0,
+        }
+    }
+
+    pub fn live(&self) -> usize {
+        self.live.len()
+    }
+
+    /// Allocates 'layout' and checks the result. Returns false if the allocator is out of memory.
+    pub fn alloc<A: HeapAllocator>(&mut self, allocator: &mut A, layout: Layout) -> bool {
+        let ptr = allocator.allocate(layout);
+        if ptr.is_null() {
+            return false;
+        }
+        let start = ptr as usize;
+        let end = start + layout.size();
+        assert_eq!(start % layout.align(), 0, "{:?} misaligned at {:#x}", layout, start);
+        assert!(
+            start >= self.heap_start && end <= self.heap_end,
+            "{:?} at {:#x} outside of the heap", layout, start
+        );
+        if let Some((&prev, (prev_layout, _))) = self.live.range(..start).next_back() {
+            assert!(prev + prev_layout.size() <= start, "{:#x} overlaps {:#x}", start, prev);
+        }
+        if let Some((&next, _)) = self.live.range(start..).next() {
+            assert!(end <= next, "{:#x} overlaps {:#x}", start, next);
+        }
+        self.fill = self.fill.wrapping_add(1);
+        unsafe { ptr.write_bytes(self.fill, layout.size()) };
+        self.live.insert(start, (layout, self.fill));
+        true
+    }
+
+    /// Frees the n-th live allocation, counted by address.
+    pub fn free<A: HeapAllocator>(&mut self, allocator: &mut A, n: usize) {
+        let start = *self.live.keys().nth(n).unwrap();
+        let (layout, fill) = self.live.remove(&start).unwrap();
+        let ptr = start as *mut u8;
+        let intact = (0..layout.size()).all(|i| unsafe { *ptr.add(i) } == fill);
+        assert!(intact, "{:?} at {:#x} was overwritten", layout, start);
+        unsafe { allocator.deallocate(ptr, layout) };
+    }
+
+    pub fn free_all<A: HeapAllocator>(&mut self, allocator: &mut A) {
+        while !self.live.is_empty() {
+            self.free(allocator, 0);
+        }
+    }
+
+    /// Runs a random mix of allocations and frees against the allocator.
+    pub fn run<A: HeapAllocator>(&mut self, allocator: &mut A, rng: &mut Rng, steps: usize) {
+        for _ in 0..steps {
+            // allocate a bit more often than free, so the heap fills up over time
+            if self.live.is_empty() || rng.below(5) < 3 {
+                let layout = rng.layout();
+                self.alloc(allocator, layout);
+            } else {
+                let n = rng.below(self.live.len());
+                self.free(allocator, n);
+            }
+        }
+    }
+}
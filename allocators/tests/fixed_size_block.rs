@@ -0,0 +1,95 @@
+mod common;
+
+use std::alloc::Layout;
+
+use common::{
+    Arena,
+    Checker,
+    Rng,
+    ARENA_SIZE,
+};
+use oubre_allocators::{
+    fixed_size_block::{
+        FSBAllocator,
+        BLOCK_SIZES,
+    },
+    stats::HeapStatistics,
+    HeapAllocator,
+};
+
+fn allocator(arena: &Arena, size: usize) -> FSBAllocator {
+    let mut allocator = FSBAllocator::new();
+    unsafe { allocator.init(arena.start(), size) };
+    allocator
+}
+
+#[test]
+fn random_allocations_are_recovered() {
+    for seed in 1..=20 {
+        let arena = Arena::new(ARENA_SIZE);
+        let mut allocator = allocator(&arena, ARENA_SIZE);
+        let mut checker = Checker::new(&arena);
+        checker.run(&mut allocator, &mut Rng::new(seed), 5000);
+        checker.free_all(&mut allocator);
+
+        // all slabs but one empty slab per size went back to the fallback allocator
+        let stats = allocator.stats();
+        assert!(stats.block_classes.iter().all(|class| class.slabs <= 1), "seed {}", seed);
+        assert!(stats.block_classes.iter().all(|class| class.blocks_in_use == 0), "seed {}", seed);
+        assert_eq!((stats.bytes_in_use, stats.live_allocations()), (0, 0));
+    }
+}
+
+#[test]
+fn blocks_share_slabs() {
+    let arena = Arena::new(ARENA_SIZE);
+    let mut allocator = allocator(&arena, ARENA_SIZE);
+    let mut checker = Checker::new(&arena);
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    for _ in 0..100 {
+        assert!(checker.alloc(&mut allocator, layout));
+    }
+    let class = BLOCK_SIZES.iter().position(|&size| size == 16).unwrap();
+    let stats = allocator.stats().block_classes[class];
+    assert_eq!((stats.blocks_in_use, stats.slabs), (100, 1));
+    checker.free_all(&mut allocator);
+    assert_eq!(allocator.stats().block_classes[class].slabs, 1);
+}
+
+#[test]
+fn one_empty_slab_is_kept_per_size() {
+    let arena = Arena::new(ARENA_SIZE);
+    let mut allocator = allocator(&arena, ARENA_SIZE);
+    let mut checker = Checker::new(&arena);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let class = BLOCK_SIZES.iter().position(|&size| size == 64).unwrap();
+
+    // freeing the only block keeps its slab for the next allocation
+    for _ in 0..100 {
+        assert!(checker.alloc(&mut allocator, layout));
+        checker.free_all(&mut allocator);
+        assert_eq!(allocator.stats().block_classes[class].slabs, 1);
+    }
+    // a second slab that empties is released
+    for _ in 0..1000 {
+        assert!(checker.alloc(&mut allocator, layout));
+    }
+    assert!(allocator.stats().block_classes[class].slabs > 2);
+    checker.free_all(&mut allocator);
+    assert_eq!(allocator.stats().block_classes[class].slabs, 1);
+}
+
+#[test]
+fn extend_makes_room_for_slabs() {
+    let arena = Arena::new(16 * 4096);
+    let mut allocator = allocator(&arena, 4096);
+    let mut checker = Checker::new(&arena);
+    let layout = Layout::from_size_align(2048, 8).unwrap();
+    assert!(!checker.alloc(&mut allocator, layout));
+
+    let growth = allocator.min_growth(&layout);
+    assert!(growth <= 15 * 4096);
+    unsafe { allocator.extend(15 * 4096) };
+    assert!(checker.alloc(&mut allocator, layout));
+    checker.free_all(&mut allocator);
+}
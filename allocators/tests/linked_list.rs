@@ -0,0 +1,83 @@
+mod common;
+
+use std::alloc::Layout;
+
+use common::{
+    Arena,
+    Checker,
+    Rng,
+    ARENA_SIZE,
+};
+use oubre_allocators::{
+    linked_list::LinkedListAllocator,
+    stats::HeapStatistics,
+    HeapAllocator,
+};
+
+fn allocator(arena: &Arena, size: usize) -> LinkedListAllocator {
+    let mut allocator = LinkedListAllocator::new();
+    unsafe { allocator.init(arena.start(), size) };
+    allocator
+}
+
+#[test]
+fn random_allocations_are_recovered() {
+    for seed in 1..=20 {
+        let arena = Arena::new(ARENA_SIZE);
+        let mut allocator = allocator(&arena, ARENA_SIZE);
+        let mut checker = Checker::new(&arena);
+        checker.run(&mut allocator, &mut Rng::new(seed), 5000);
+        checker.free_all(&mut allocator);
+
+        // everything merged back into a single region
+        let stats = allocator.stats();
+        assert_eq!(stats.largest_free_region, Some(ARENA_SIZE), "seed {}", seed);
+        assert_eq!((stats.bytes_in_use, stats.live_allocations()), (0, 0));
+        assert!(checker.alloc(&mut allocator, Layout::from_size_align(ARENA_SIZE, 8).unwrap()));
+    }
+}
+
+#[test]
+fn full_heap_returns_null() {
+    let arena = Arena::new(4096);
+    let mut allocator = allocator(&arena, 4096);
+    let mut checker = Checker::new(&arena);
+    let layout = Layout::from_size_align(1024, 8).unwrap();
+    for _ in 0..4 {
+        assert!(checker.alloc(&mut allocator, layout));
+    }
+    assert!(!checker.alloc(&mut allocator, Layout::from_size_align(8, 8).unwrap()));
+    checker.free_all(&mut allocator);
+}
+
+#[test]
+fn extend_merges_with_last_region() {
+    let arena = Arena::new(2 * 4096);
+    let mut allocator = allocator(&arena, 4096);
+    let mut checker = Checker::new(&arena);
+    let layout = Layout::from_size_align(6000, 8).unwrap();
+    assert!(!checker.alloc(&mut allocator, layout));
+    assert!(allocator.min_growth(&layout) >= 6000);
+
+    unsafe { allocator.extend(4096) };
+    assert_eq!(allocator.top(), arena.start() + 2 * 4096);
+    assert!(checker.alloc(&mut allocator, layout));
+    checker.free_all(&mut allocator);
+    assert_eq!(allocator.stats().largest_free_region, Some(2 * 4096));
+}
+
+#[test]
+fn free_regions_are_listed_in_order() {
+    let arena = Arena::new(4096);
+    let mut allocator = allocator(&arena, 4096);
+    let layout = Layout::from_size_align(512, 8).unwrap();
+    let blocks: Vec<_> = (0..8).map(|_| allocator.allocate(layout)).collect();
+    unsafe {
+        allocator.deallocate(blocks[1], layout);
+        allocator.deallocate(blocks[5], layout);
+        allocator.deallocate(blocks[4], layout);
+    }
+    let mut regions = Vec::new();
+    allocator.for_each_free_region(&mut |start, size| regions.push((start, size)));
+    assert_eq!(regions, [(blocks[1] as usize, 512), (blocks[4] as usize, 1024)]);
+}
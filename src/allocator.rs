@@ -1,7 +1,15 @@
 /// Allocators 
-pub mod bump;
-pub mod linked_list;
-pub mod fixed_size_block;
+pub use oubre_allocators::{
+    bump,
+    linked_list,
+    fixed_size_block,
+    stats,
+};
+pub mod slab_cache;
+pub mod debug;
+pub mod tracking;
+pub mod oom;
+pub mod fallible;
 
 use alloc::alloc::{
     GlobalAlloc,
@@ -17,31 +25,114 @@ use x86_64::{
     structures::paging::{
         mapper::MapToError,
         Mapper,
+        Translate,
         Page,
         PageTableFlags,
         FrameAllocator,
         Size4KiB,
+        PageSize,
     },
     VirtAddr,
 };
 
-use core::ptr::null_mut;
+use crate::memory::{
+    self,
+    vma::{
+        RegionKind,
+        VIRTUAL_REGIONS,
+    },
+};
+
+use core::{
+    ptr::null_mut,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+};
 
 use linked_list_allocator::LockedHeap;
 
+use oubre_allocators::{
+    align_up,
+    HeapAllocator,
+};
 use bump::BumpAllocator;
 use linked_list::LinkedListAllocator;
 use fixed_size_block::FSBAllocator;
+use debug::DebugAllocator;
+use tracking::TrackingAllocator;
+use stats::{
+    HeapStatistics,
+    HeapStats,
+};
 
+pub use fallible::{
+    try_alloc,
+    try_box,
+    try_vec_with_capacity,
+    AllocError,
+};
+
+/// The heap starts here and grows upwards. The page in front of it and the page
+/// behind its current end are never mapped, so overflows fault instead of
+/// corrupting memory, see `is_heap_guard`.
 pub const HEAP_START: usize = 0x_4444_4444_0000;
 pub const HEAP_SIZE: usize = 100 * 1024; // 100KiB
+/// The default limit the heap may grow to when it runs out of memory
+pub const HEAP_MAX_SIZE: usize = 16 * 1024 * 1024; // 16MiB
+/// Virtual address range kept for the heap, it can never grow beyond this
+pub const HEAP_RESERVED_SIZE: usize = 1024 * 1024 * 1024; // 1GiB
+
+// current limit of the heap size, see set_heap_max_size
+static HEAP_MAX: AtomicUsize = AtomicUsize::new(HEAP_MAX_SIZE);
+// end of the mapped heap
+static HEAP_END: AtomicUsize = AtomicUsize::new(HEAP_START);
 
+// The global allocator is picked with the alloc-* cargo features, e.g.
+// cargo build --no-default-features --features alloc-bump
+#[cfg(feature = "alloc-fsb")]
+type KernelAllocator = Locked<FSBAllocator>;
+#[cfg(feature = "alloc-fsb")]
+const fn kernel_allocator() -> KernelAllocator { Locked::new(FSBAllocator::new()) }
+#[cfg(feature = "alloc-linked-list")]
+type KernelAllocator = Locked<LinkedListAllocator>;
+#[cfg(feature = "alloc-linked-list")]
+const fn kernel_allocator() -> KernelAllocator { Locked::new(LinkedListAllocator::new()) }
+#[cfg(feature = "alloc-bump")]
+type KernelAllocator = Locked<BumpAllocator>;
+#[cfg(feature = "alloc-bump")]
+const fn kernel_allocator() -> KernelAllocator { Locked::new(BumpAllocator::new()) }
+#[cfg(feature = "alloc-external")]
+type KernelAllocator = LockedHeap;
+#[cfg(feature = "alloc-external")]
+const fn kernel_allocator() -> KernelAllocator { LockedHeap::empty() }
+
+// the alloc-debug feature wraps the chosen allocator with guard bytes and poisoning
+// allocations are always recorded on request, see tracking::start_tracking
+#[cfg(not(feature = "alloc-debug"))]
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator<KernelAllocator> = TrackingAllocator::new(kernel_allocator());
+#[cfg(feature = "alloc-debug")]
 #[global_allocator]
-static ALLOCATOR: Locked<FSBAllocator> = Locked::new(FSBAllocator::new());
-// static ALLOCATOR: Locked<LinkedListAllocator> = Locked::new(LinkedListAllocator::new());
-// static ALLOCATOR: Locked<BumpAllocator> = Locked::new(BumpAllocator::new());
+static ALLOCATOR: TrackingAllocator<DebugAllocator<KernelAllocator>> =
+    TrackingAllocator::new(DebugAllocator::new(kernel_allocator()));
 // static ALLOCATOR: Dummy = Dummy; 
-// static ALLOCATOR: LockedHeap = LockedHeap::empty();
+
+#[cfg(not(any(
+    feature = "alloc-fsb",
+    feature = "alloc-linked-list",
+    feature = "alloc-bump",
+    feature = "alloc-external",
+)))]
+compile_error!("no global allocator selected, enable one of the alloc-* features");
+
+#[cfg(any(
+    all(feature = "alloc-fsb", any(feature = "alloc-linked-list", feature = "alloc-bump", feature = "alloc-external")),
+    all(feature = "alloc-linked-list", any(feature = "alloc-bump", feature = "alloc-external")),
+    all(feature = "alloc-bump", feature = "alloc-external"),
+))]
+compile_error!("more than one global allocator selected, enable only one of the alloc-* features");
 
 pub struct Dummy; 
 
@@ -57,12 +148,56 @@ unsafe impl GlobalAlloc for Dummy {
 
 }
 
+// drives the kernel's own allocators, growing the heap when they run out of memory
+unsafe impl<A: HeapAllocator> GlobalAlloc for Locked<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.alloc_or_grow(layout);
+        if !ptr.is_null() {
+            return ptr;
+        }
+        // the OOM handler may free memory, then try again
+        for _ in 0..oom::RETRIES {
+            if !oom::reclaim(layout) {
+                break;
+            }
+            let ptr = self.alloc_or_grow(layout);
+            if !ptr.is_null() {
+                return ptr;
+            }
+        }
+        null_mut()
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.lock().deallocate(ptr, layout);
+    }
+}
+
+impl<A: HeapAllocator> Locked<A> {
+    // the lock is released on return, so the OOM handler can free memory
+    unsafe fn alloc_or_grow(&self, layout: Layout) -> *mut u8 {
+        let mut allocator = self.lock();
+        let ptr = allocator.allocate(layout);
+        if !ptr.is_null() {
+            return ptr;
+        }
+        // out of memory, map more pages at the end of the heap and retry
+        match grow_heap(allocator.top(), allocator.min_growth(&layout)) {
+            Some(size) => {
+                allocator.extend(size);
+                allocator.allocate(layout)
+            }
+            None => null_mut(),
+        }
+    }
+}
+
 pub fn init_heap<M, F>(
     mapper: &mut M,
     frame_allocator: &mut F
 ) -> Result<(), MapToError<Size4KiB>>
 where
-    M: Mapper<Size4KiB>,
+    M: Mapper<Size4KiB> + Translate,
     F: FrameAllocator<Size4KiB>,
 {
     // creating a page range
@@ -74,12 +209,18 @@ where
         Page::range_inclusive(heap_start_page, heap_end_page)
     };
 
+    VIRTUAL_REGIONS.lock()
+        .reserve(VirtAddr::new(HEAP_START as u64), HEAP_RESERVED_SIZE as u64, RegionKind::Heap, "kernel heap")
+        .expect("the heap's virtual range is taken");
+    // nothing may be mapped right next to the heap
+    memory::check_guard_pages(mapper, Page::range(page_range.start, page_range.end + 1))?;
+
     // mapping the pages of the page range
     for page in page_range {
         let frame = frame_allocator
         .allocate_frame()
         .ok_or(MapToError::FrameAllocationFailed)?;
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
         unsafe {
             mapper.map_to(page, frame, flags, frame_allocator)?.flush()
         };
@@ -88,10 +229,58 @@ where
     unsafe {
         ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
     }
+    HEAP_END.store(HEAP_START + HEAP_SIZE, Ordering::Relaxed);
 
     Ok(())
 }
 
+/// Returns the usage statistics of the global allocator.
+pub fn heap_stats() -> HeapStats {
+    ALLOCATOR.lock().stats()
+}
+
+/// Returns true if 'addr' lies in one of the guard pages around the heap,
+/// which means something ran over the start or the end of the heap.
+pub fn is_heap_guard(addr: VirtAddr) -> bool {
+    let addr = addr.as_u64() as usize;
+    let heap_end = HEAP_END.load(Ordering::Relaxed);
+    let page_size = Size4KiB::SIZE as usize;
+    (HEAP_START - page_size..HEAP_START).contains(&addr)
+        || (heap_end..heap_end + page_size).contains(&addr)
+}
+
+/// Sets the size the heap may grow to, at most HEAP_RESERVED_SIZE.
+/// The heap never shrinks below its current size.
+pub fn set_heap_max_size(size: usize) {
+    HEAP_MAX.store(size.min(HEAP_RESERVED_SIZE), Ordering::Relaxed);
+}
+
+/// Maps more memory at the current end of the heap 'heap_end', adding at least
+/// 'min_size' bytes. Returns the number of bytes the heap grew by.
+///
+/// Called by the allocators when they run out of memory. Fails if the heap
+/// would grow beyond its maximum size, if no frames are left, or if the kernel
+/// memory has not been handed over with `memory::init_kernel_memory` yet.
+fn grow_heap(heap_end: usize, min_size: usize) -> Option<usize> {
+    if heap_end < HEAP_START {
+        // the allocator was never initialized or doesn't manage the kernel heap
+        return None;
+    }
+    let heap_max_end = HEAP_START + HEAP_MAX.load(Ordering::Relaxed);
+    // grow in steps of at least HEAP_SIZE, so small allocations don't map page by page
+    let size = align_up(min_size.max(HEAP_SIZE), Size4KiB::SIZE as usize)
+        .min(heap_max_end.saturating_sub(heap_end));
+    if size < min_size {
+        return None;
+    }
+
+    // big heaps end up on 2MiB pages once the growth reaches an aligned address
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    let mut kernel_memory = memory::KERNEL_MEMORY.lock();
+    kernel_memory.as_mut()?.map_range(VirtAddr::new(heap_end as u64), size as u64, flags).ok()?;
+    HEAP_END.store(heap_end + size, Ordering::Relaxed);
+    Some(size)
+}
 
 /// A wrapper around spin::Mutex to permit trait implementations
 pub struct Locked<A> {
@@ -109,14 +298,3 @@ impl<A> Locked<A> {
         self.inner.lock()
     }
 }
-
-/// Align the given address 'addr' upwards to alignment 'align'.
-fn align_up(addr: usize, align: usize) -> usize {
-    // ( addr + align - 1) & !(align -1)
-    let remainder = addr % align;
-    if remainder == 0 {
-        addr // addr already aligned
-    } else {
-        addr - remainder + align
-    }
-}
\ No newline at end of file
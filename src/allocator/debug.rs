@@ -0,0 +1,212 @@
+use alloc::alloc::{
+    GlobalAlloc,
+    Layout,
+};
+
+use core::{
+    arch::asm,
+    mem,
+    ops::Deref,
+    ptr::{
+        self,
+        null_mut,
+    },
+};
+
+use x86_64::VirtAddr;
+
+use super::align_up;
+use crate::{
+    gdt,
+    serial_println,
+};
+
+/// bytes of guard on each side of an allocation
+const GUARD_SIZE: usize = 16;
+const GUARD_BYTE: u8 = 0xfd;
+/// freed memory is filled with this, so use-after-free reads stand out
+const POISON_BYTE: u8 = 0xdd;
+/// fresh allocations are filled with this instead of whatever was there before
+const UNINIT_BYTE: u8 = 0xcd;
+
+const MAGIC_ALLOCATED: u64 = 0xa110_ca7e_d0d0_a110;
+const MAGIC_FREED: u64 = 0xf4ee_d0d0_f4ee_d0d0;
+
+/// number of return addresses recorded for each allocation
+const CALLERS: usize = 4;
+
+/// Sits in front of the front guard of every allocation.
+struct Header {
+    magic: u64,
+    size: usize,
+    // return addresses of the allocating call chain, innermost first
+    callers: [usize; CALLERS],
+}
+
+/// Ways in which an allocation can be found corrupted when it is freed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corruption {
+    /// the header was overwritten, or the memory was freed before
+    Header,
+    /// the allocation was freed with a different size than it was allocated with
+    SizeMismatch,
+    /// a byte in front of the allocation was overwritten
+    FrontGuard,
+    /// a byte behind the allocation was overwritten
+    BackGuard,
+}
+
+/// A debug wrapper around any allocator that detects heap corruption.
+///
+/// Every allocation gets a header and guard bytes before and after it. On free,
+/// the header and guards are checked, and the memory is poisoned before it goes back
+/// to the wrapped allocator. When corruption is found, the offending Layout and the
+/// call chains of the allocation and the free are printed over serial, then the
+/// kernel panics.
+///
+/// Caller addresses are found by walking the frame pointers, so they are only
+/// meaningful when the kernel is built with frame pointers, e.g. through
+/// `cargo test-alloc-debug` or RUSTFLAGS="-C force-frame-pointers=yes".
+pub struct DebugAllocator<A> {
+    inner: A,
+}
+
+impl<A> DebugAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        DebugAllocator { inner }
+    }
+
+    // offset from the start of the block to the allocation, covering header and front guard
+    fn front_size(layout: &Layout) -> usize {
+        align_up(mem::size_of::<Header>() + GUARD_SIZE, Self::block_align(layout))
+    }
+
+    fn block_align(layout: &Layout) -> usize {
+        layout.align().max(mem::align_of::<Header>())
+    }
+
+    // layout of the whole block, as requested from the wrapped allocator
+    fn block_layout(layout: &Layout) -> Layout {
+        let size = Self::front_size(layout) + layout.size() + GUARD_SIZE;
+        Layout::from_size_align(size, Self::block_align(layout)).unwrap()
+    }
+
+    /// Checks the header and guards of a live allocation.
+    ///
+    /// This function is unsafe because 'ptr' must have been returned by this allocator
+    /// for the given layout.
+    pub unsafe fn check(&self, ptr: *mut u8, layout: Layout) -> Result<(), Corruption> {
+        let front_size = Self::front_size(&layout);
+        let block = ptr.sub(front_size);
+        let header = &*(block as *const Header);
+        if header.magic != MAGIC_ALLOCATED {
+            return Err(Corruption::Header);
+        }
+        if header.size != layout.size() {
+            return Err(Corruption::SizeMismatch);
+        }
+        let front_guard = block.add(mem::size_of::<Header>());
+        if !is_filled(front_guard, front_size - mem::size_of::<Header>(), GUARD_BYTE) {
+            return Err(Corruption::FrontGuard);
+        }
+        if !is_filled(ptr.add(layout.size()), GUARD_SIZE, GUARD_BYTE) {
+            return Err(Corruption::BackGuard);
+        }
+        Ok(())
+    }
+
+    // prints what is known about the corrupted allocation and stops the kernel
+    unsafe fn report(&self, corruption: Corruption, ptr: *mut u8, layout: Layout) -> ! {
+        let header = &*(ptr.sub(Self::front_size(&layout)) as *const Header);
+        serial_println!("HEAP CORRUPTION DETECTED: {:?}", corruption);
+        serial_println!("    allocation: {:p} {:?}", ptr, layout);
+        if corruption != Corruption::Header {
+            serial_println!("    allocated from: {:x?}", header.callers);
+        }
+        serial_println!("    freed from: {:x?}", callers());
+        panic!("heap corruption detected at {:p}: {:?}", ptr, corruption);
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for DebugAllocator<A> {
+    #[inline(never)]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let block_layout = Self::block_layout(&layout);
+        let block = self.inner.alloc(block_layout);
+        if block.is_null() {
+            return null_mut();
+        }
+        let front_size = Self::front_size(&layout);
+        (block as *mut Header).write(Header {
+            magic: MAGIC_ALLOCATED,
+            size: layout.size(),
+            callers: callers(),
+        });
+        let ptr = block.add(front_size);
+        let front_guard = block.add(mem::size_of::<Header>());
+        ptr::write_bytes(front_guard, GUARD_BYTE, front_size - mem::size_of::<Header>());
+        ptr::write_bytes(ptr, UNINIT_BYTE, layout.size());
+        ptr::write_bytes(ptr.add(layout.size()), GUARD_BYTE, GUARD_SIZE);
+        ptr
+    }
+
+    #[inline(never)]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Err(corruption) = self.check(ptr, layout) {
+            self.report(corruption, ptr, layout);
+        }
+        let block_layout = Self::block_layout(&layout);
+        let block = ptr.sub(Self::front_size(&layout));
+        ptr::write_bytes(block, POISON_BYTE, block_layout.size());
+        (*(block as *mut Header)).magic = MAGIC_FREED;
+        self.inner.dealloc(block, block_layout);
+    }
+}
+
+// gives access to the wrapped allocator, e.g. to initialize it
+impl<A> Deref for DebugAllocator<A> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        &self.inner
+    }
+}
+
+unsafe fn is_filled(start: *const u8, len: usize, byte: u8) -> bool {
+    (0..len).all(|i| *start.add(i) == byte)
+}
+
+/// Collects the return addresses of the current call chain by following the saved
+/// frame pointers. Stops early at anything that doesn't look like a stack frame.
+///
+/// Only frames on the stack the call runs on are read, the outermost frame holds
+/// whatever the bootloader left in rbp, which may point to unmapped memory.
+#[inline(always)]
+pub fn callers() -> [usize; CALLERS] {
+    let mut callers = [0; CALLERS];
+    let (mut frame, stack_pointer): (usize, usize);
+    unsafe { asm!("mov {}, rbp", "mov {}, rsp", out(reg) frame, out(reg) stack_pointer) };
+    let stack = match gdt::stack_containing(VirtAddr::new(stack_pointer as u64)) {
+        Some(stack) => stack,
+        None => return callers,
+    };
+    for caller in callers.iter_mut() {
+        // the saved frame pointer and the return address must both be on the stack
+        if frame < stack_pointer
+            || frame as u64 + 16 > stack.end.as_u64()
+            || frame % mem::align_of::<usize>() != 0
+        {
+            break;
+        }
+        let (next_frame, return_addr) = unsafe {
+            (*(frame as *const usize), *((frame + 8) as *const usize))
+        };
+        *caller = return_addr;
+        // the stack grows down, so the frames of callers sit at higher addresses
+        if next_frame <= frame {
+            break;
+        }
+        frame = next_frame;
+    }
+    callers
+}
@@ -0,0 +1,65 @@
+use alloc::{
+    alloc::{
+        alloc,
+        Layout,
+    },
+    boxed::Box,
+    vec::Vec,
+};
+
+use core::{
+    fmt,
+    mem,
+    ptr::NonNull,
+};
+
+/// The heap had no room for an allocation, even after growing and running the OOM handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError {
+    /// None if the requested size doesn't fit in a Layout
+    pub layout: Option<Layout>,
+}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.layout {
+            Some(layout) => write!(f, "out of memory allocating {:?}", layout),
+            None => write!(f, "allocation size overflow"),
+        }
+    }
+}
+
+/// Allocates memory for 'layout' from the global allocator, like `alloc::alloc::alloc`,
+/// but returns an error instead of a null pointer. Zero sized layouts get a dangling pointer.
+///
+/// The memory has to be freed with `alloc::alloc::dealloc` and the same layout.
+pub fn try_alloc(layout: Layout) -> Result<NonNull<u8>, AllocError> {
+    if layout.size() == 0 {
+        // the alignment is a non zero power of 2, so this is a valid dangling pointer
+        return Ok(unsafe { NonNull::new_unchecked(layout.align() as *mut u8) });
+    }
+    NonNull::new(unsafe { alloc(layout) }).ok_or(AllocError { layout: Some(layout) })
+}
+
+/// Moves 'value' to the heap, returns an error instead of calling the alloc error handler.
+pub fn try_box<T>(value: T) -> Result<Box<T>, AllocError> {
+    if mem::size_of::<T>() == 0 {
+        // boxes of zero sized types don't allocate
+        return Ok(Box::new(value));
+    }
+    let ptr = try_alloc(Layout::new::<T>())?.as_ptr() as *mut T;
+    unsafe {
+        ptr.write(value);
+        Ok(Box::from_raw(ptr))
+    }
+}
+
+/// Creates an empty Vec with room for exactly 'capacity' elements,
+/// returns an error instead of calling the alloc error handler.
+pub fn try_vec_with_capacity<T>(capacity: usize) -> Result<Vec<T>, AllocError> {
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(capacity).map_err(|_| AllocError {
+        layout: Layout::array::<T>(capacity).ok(),
+    })?;
+    Ok(vec)
+}
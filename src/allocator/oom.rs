@@ -0,0 +1,90 @@
+use alloc::alloc::Layout;
+
+use core::{
+    fmt,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
+
+use spin::Mutex;
+
+use super::stats::HeapStatistics;
+use crate::serial_println;
+
+/// How often an allocation is retried after the OOM handler freed memory.
+pub(super) const RETRIES: usize = 3;
+
+/// free regions listed in the report, the list of a fragmented heap can be long
+const MAX_REPORTED_REGIONS: usize = 32;
+
+static OOM_HANDLER: Mutex<Option<fn(Layout) -> bool>> = Mutex::new(None);
+// set while the handler runs, its own allocations must not call it again
+static IN_HANDLER: AtomicBool = AtomicBool::new(false);
+
+/// Registers a handler that is called when an allocation of the given layout fails
+/// even after growing the heap, e.g. to shrink caches. It returns true if it
+/// freed memory, then the allocation is retried. None removes the handler.
+pub fn set_oom_handler(handler: Option<fn(Layout) -> bool>) {
+    *OOM_HANDLER.lock() = handler;
+}
+
+/// Runs the OOM handler, returns true if the failed allocation should be retried.
+/// Must not be called with the allocator locked, the handler frees memory.
+pub(super) fn reclaim(layout: Layout) -> bool {
+    let handler = match *OOM_HANDLER.lock() {
+        Some(handler) => handler,
+        None => return false,
+    };
+    if IN_HANDLER.swap(true, Ordering::Acquire) {
+        return false;
+    }
+    let freed = handler(layout);
+    IN_HANDLER.store(false, Ordering::Release);
+    freed
+}
+
+/// Prints the state of the global allocator over serial, called when an
+/// allocation of 'layout' could not be satisfied.
+pub fn report(layout: Layout) {
+    let stats = super::heap_stats();
+    // nothing here may allocate, so no string is built for the unknown case
+    let peak: &dyn fmt::Display = match stats.peak_bytes_in_use {
+        Some(ref peak) => peak,
+        None => &"unknown",
+    };
+    serial_println!("OUT OF MEMORY: no room for {:?}", layout);
+    serial_println!(
+        "    heap: {} bytes, {} in use (peak {}), {} free",
+        stats.heap_size,
+        stats.bytes_in_use,
+        peak,
+        stats.free_bytes
+    );
+    serial_println!("    live allocations: {}", stats.live_allocations());
+    if let Some(largest) = stats.largest_free_region {
+        serial_println!("    largest free region: {} bytes", largest);
+    }
+
+    let mut regions = 0;
+    super::ALLOCATOR.lock().for_each_free_region(&mut |start, size| {
+        if regions < MAX_REPORTED_REGIONS {
+            serial_println!("    free region: {:#x}..{:#x} ({} bytes)", start, start + size, size);
+        }
+        regions += 1;
+    });
+    if regions > MAX_REPORTED_REGIONS {
+        serial_println!("    ... {} more free regions", regions - MAX_REPORTED_REGIONS);
+    }
+
+    for class in stats.block_classes.iter().filter(|class| class.slabs > 0) {
+        serial_println!(
+            "    {} byte blocks: {} in use, {} free, {} slabs",
+            class.block_size,
+            class.blocks_in_use,
+            class.free_blocks,
+            class.slabs
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use alloc::alloc::{
+    alloc,
+    dealloc,
+    Layout,
+};
+
+use core::{
+    marker::PhantomData,
+    mem,
+    ops::{
+        Deref,
+        DerefMut,
+    },
+    ptr::{
+        self,
+        NonNull,
+    },
+};
+
+use spin::Mutex;
+
+use super::align_up;
+
+// a slab holds at most as many objects as bits in its free mask
+const MAX_OBJECTS_PER_SLAB: usize = 64;
+// slabs of small objects are kept at about a page
+const TARGET_SLAB_SIZE: usize = 4096;
+
+/// Header at the start of every slab, followed by the objects.
+struct SlabHeader {
+    // bit n set -> object n is free
+    free: u64,
+    // the slabs of a cache form a list, so adding one never allocates
+    next: Option<NonNull<SlabHeader>>,
+}
+
+/// A cache of objects of a single type, like a kernel slab cache.
+///
+/// Objects live in slabs taken from the global allocator. When a slab is created,
+/// all its objects are built with the cache's constructor. Freed objects are not
+/// dropped but stay in their constructed state and are handed out again as they are,
+/// so the constructor only runs again for new slabs. Objects are dropped when the
+/// slab holding them is released with `shrink` or when the cache itself is dropped.
+///
+/// New slabs are taken from the global allocator without the cache locked, so an
+/// OOM handler can shrink the cache while it grows.
+pub struct SlabCache<T> {
+    constructor: fn() -> T,
+    inner: Mutex<CacheInner>,
+    _objects: PhantomData<T>,
+}
+
+struct CacheInner {
+    name: &'static str,
+    slabs: Option<NonNull<SlabHeader>>,
+    slab_count: usize,
+    allocations: usize,
+    frees: usize,
+}
+
+/// A snapshot of the usage of a SlabCache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    pub slabs: usize,
+    pub objects_in_use: usize,
+    pub free_objects: usize,
+    /// number of allocations made so far
+    pub allocations: usize,
+    /// number of objects given back so far
+    pub frees: usize,
+}
+
+// the raw slab pointers are only touched with the lock held
+unsafe impl<T: Send> Send for SlabCache<T> {}
+unsafe impl<T: Send> Sync for SlabCache<T> {}
+
+impl<T> SlabCache<T> {
+    /// Creates an empty cache, no memory is taken until the first allocation.
+    pub const fn new(name: &'static str, constructor: fn() -> T) -> Self {
+        SlabCache {
+            constructor,
+            inner: Mutex::new(CacheInner {
+                name,
+                slabs: None,
+                slab_count: 0,
+                allocations: 0,
+                frees: 0,
+            }),
+            _objects: PhantomData,
+        }
+    }
+
+    /// Hands out a constructed object, adding a slab if all objects are in use.
+    /// Returns None if the global allocator is out of memory.
+    pub fn alloc(&self) -> Option<CacheBox<T>> {
+        let mut inner = self.inner.lock();
+        let slab = match inner.slabs().find(|slab| unsafe { slab.as_ref().free } != 0) {
+            Some(slab) => slab,
+            None => {
+                // the global allocator may call the OOM handler, which may shrink this cache
+                drop(inner);
+                let slab = self.new_slab()?;
+                inner = self.inner.lock();
+                unsafe { (*slab.as_ptr()).next = inner.slabs };
+                inner.slabs = Some(slab);
+                inner.slab_count += 1;
+                slab
+            }
+        };
+        inner.allocations += 1;
+        unsafe {
+            let header = &mut *slab.as_ptr();
+            let index = header.free.trailing_zeros() as usize;
+            header.free &= !(1 << index);
+            Some(CacheBox {
+                cache: self,
+                object: NonNull::new_unchecked(Self::object(slab, index)),
+            })
+        }
+    }
+
+    /// Releases all slabs whose objects are all free, dropping their objects.
+    /// Returns the number of slabs released.
+    pub fn shrink(&self) -> usize {
+        let mut inner = self.inner.lock();
+        let all_free = Self::all_free_mask();
+        let mut released = 0;
+        let mut link = &mut inner.slabs;
+        while let Some(slab) = *link {
+            let header = unsafe { &mut *slab.as_ptr() };
+            if header.free == all_free {
+                *link = header.next;
+                unsafe { Self::release_slab(slab) };
+                released += 1;
+            } else {
+                link = &mut header.next;
+            }
+        }
+        inner.slab_count -= released;
+        released
+    }
+
+    /// Reports how the cache is used.
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        let free_objects = inner.slabs()
+            .map(|slab| unsafe { slab.as_ref().free }.count_ones() as usize)
+            .sum();
+        let objects = inner.slab_count * Self::objects_per_slab();
+        CacheStats {
+            name: inner.name,
+            object_size: mem::size_of::<T>(),
+            objects_per_slab: Self::objects_per_slab(),
+            slabs: inner.slab_count,
+            objects_in_use: objects - free_objects,
+            free_objects,
+            allocations: inner.allocations,
+            frees: inner.frees,
+        }
+    }
+
+    fn free(&self, object: NonNull<T>) {
+        let mut inner = self.inner.lock();
+        // slabs are aligned to their size, so the header is found by rounding down
+        let slab_addr = object.as_ptr() as usize & !(Self::slab_layout().size() - 1);
+        let index = (object.as_ptr() as usize - slab_addr - Self::objects_offset())
+            / Self::object_stride();
+        let header = unsafe { &mut *(slab_addr as *mut SlabHeader) };
+        assert_eq!(header.free & (1 << index), 0, "object freed twice");
+        header.free |= 1 << index;
+        inner.frees += 1;
+    }
+
+    // size taken by one object in a slab, ZSTs still get their own slot
+    fn object_stride() -> usize {
+        align_up(mem::size_of::<T>().max(1), mem::align_of::<T>())
+    }
+
+    fn objects_offset() -> usize {
+        align_up(mem::size_of::<SlabHeader>(), mem::align_of::<T>())
+    }
+
+    fn objects_per_slab() -> usize {
+        (TARGET_SLAB_SIZE / Self::object_stride()).clamp(1, MAX_OBJECTS_PER_SLAB)
+    }
+
+    fn all_free_mask() -> u64 {
+        u64::MAX >> (MAX_OBJECTS_PER_SLAB - Self::objects_per_slab())
+    }
+
+    fn slab_layout() -> Layout {
+        let size = (Self::objects_offset() + Self::objects_per_slab() * Self::object_stride())
+            .next_power_of_two();
+        Layout::from_size_align(size, size).unwrap()
+    }
+
+    fn object(slab: NonNull<SlabHeader>, index: usize) -> *mut T {
+        let offset = Self::objects_offset() + index * Self::object_stride();
+        unsafe { (slab.as_ptr() as *mut u8).add(offset) as *mut T }
+    }
+
+    // allocates a slab and runs the constructor for all its objects
+    fn new_slab(&self) -> Option<NonNull<SlabHeader>> {
+        let slab = NonNull::new(unsafe { alloc(Self::slab_layout()) } as *mut SlabHeader)?;
+        unsafe {
+            slab.as_ptr().write(SlabHeader {
+                free: Self::all_free_mask(),
+                next: None,
+            });
+            for index in 0..Self::objects_per_slab() {
+                Self::object(slab, index).write((self.constructor)());
+            }
+        }
+        Some(slab)
+    }
+
+    // drops all objects of the slab and gives its memory back
+    unsafe fn release_slab(slab: NonNull<SlabHeader>) {
+        for index in 0..Self::objects_per_slab() {
+            ptr::drop_in_place(Self::object(slab, index));
+        }
+        dealloc(slab.as_ptr() as *mut u8, Self::slab_layout());
+    }
+}
+
+impl CacheInner {
+    fn slabs(&self) -> impl Iterator<Item = NonNull<SlabHeader>> {
+        core::iter::successors(self.slabs, |slab| unsafe { slab.as_ref().next })
+    }
+}
+
+impl<T> Drop for SlabCache<T> {
+    fn drop(&mut self) {
+        // no CacheBox can outlive the cache, so all objects are free
+        let mut next = self.inner.lock().slabs.take();
+        while let Some(slab) = next {
+            next = unsafe { slab.as_ref().next };
+            unsafe { Self::release_slab(slab) };
+        }
+    }
+}
+
+/// An object handed out by a SlabCache, it goes back to the cache when dropped.
+pub struct CacheBox<'a, T> {
+    cache: &'a SlabCache<T>,
+    object: NonNull<T>,
+}
+
+unsafe impl<'a, T: Send> Send for CacheBox<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for CacheBox<'a, T> {}
+
+impl<'a, T> Deref for CacheBox<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.object.as_ref() }
+    }
+}
+
+impl<'a, T> DerefMut for CacheBox<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.object.as_mut() }
+    }
+}
+
+impl<'a, T> Drop for CacheBox<'a, T> {
+    fn drop(&mut self) {
+        self.cache.free(self.object);
+    }
+}
@@ -0,0 +1,185 @@
+use alloc::alloc::{
+    GlobalAlloc,
+    Layout,
+};
+
+use core::{
+    ops::Deref,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
+
+use spin::Mutex;
+
+use crate::serial_println;
+
+/// Number of live allocations that can be tracked at the same time.
+/// Must be a power of 2.
+pub const MAX_TRACKED: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    pub addr: usize,
+    pub size: usize,
+    /// the tag that was set when the allocation was made
+    pub tag: &'static str,
+}
+
+#[derive(Clone, Copy)]
+enum Slot {
+    Empty,
+    // a removed entry, lookups have to continue past it
+    Removed,
+    Used(Allocation),
+}
+
+/// The live allocations made while tracking, in a hash table keyed by address.
+/// It can't use the heap itself, so it has a fixed size.
+struct Table {
+    slots: [Slot; MAX_TRACKED],
+    tracked: usize,
+    // allocations that didn't fit into the table and are not known to be freed
+    overflowed: usize,
+    tag: &'static str,
+}
+
+impl Table {
+    const fn new() -> Self {
+        Table {
+            slots: [Slot::Empty; MAX_TRACKED],
+            tracked: 0,
+            overflowed: 0,
+            tag: "",
+        }
+    }
+
+    // slots to probe for an address, starting at its hash
+    fn probe(addr: usize) -> impl Iterator<Item = usize> {
+        // addresses are at least 8 byte aligned, the low bits carry no information
+        let start = (addr >> 3).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (64 - MAX_TRACKED.trailing_zeros());
+        (0..MAX_TRACKED).map(move |i| (start + i) % MAX_TRACKED)
+    }
+
+    // resets the table in place, it is too large to be built on the stack
+    fn clear(&mut self) {
+        for slot in self.slots.iter_mut() {
+            *slot = Slot::Empty;
+        }
+        self.tracked = 0;
+        self.overflowed = 0;
+    }
+
+    fn insert(&mut self, addr: usize, size: usize) {
+        let allocation = Allocation { addr, size, tag: self.tag };
+        for index in Self::probe(addr) {
+            if let Slot::Empty | Slot::Removed = self.slots[index] {
+                self.slots[index] = Slot::Used(allocation);
+                self.tracked += 1;
+                return;
+            }
+        }
+        self.overflowed += 1;
+    }
+
+    fn remove(&mut self, addr: usize) {
+        for index in Self::probe(addr) {
+            match self.slots[index] {
+                Slot::Used(allocation) if allocation.addr == addr => {
+                    self.slots[index] = Slot::Removed;
+                    self.tracked -= 1;
+                    return;
+                }
+                // allocations made before tracking started are not in the table, neither
+                // are those that overflowed it. Counting this as the free of an overflowed one
+                // may miss a leak, but never reports one that isn't.
+                Slot::Empty => break,
+                _ => {}
+            }
+        }
+        self.overflowed = self.overflowed.saturating_sub(1);
+    }
+
+    fn allocations(&self) -> impl Iterator<Item = &Allocation> {
+        self.slots.iter().filter_map(|slot| match slot {
+            Slot::Used(allocation) => Some(allocation),
+            _ => None,
+        })
+    }
+}
+
+static TRACKING: AtomicBool = AtomicBool::new(false);
+static TABLE: Mutex<Table> = Mutex::new(Table::new());
+
+/// A wrapper around the global allocator that records the live allocations
+/// made between `start_tracking` and `stop_tracking`.
+/// While tracking is off it only costs an atomic load per call.
+pub struct TrackingAllocator<A> {
+    inner: A,
+}
+
+impl<A> TrackingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        TrackingAllocator { inner }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() && TRACKING.load(Ordering::Relaxed) {
+            TABLE.lock().insert(ptr as usize, layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if TRACKING.load(Ordering::Relaxed) {
+            TABLE.lock().remove(ptr as usize);
+        }
+        self.inner.dealloc(ptr, layout);
+    }
+}
+
+// gives access to the wrapped allocator, e.g. to initialize it
+impl<A> Deref for TrackingAllocator<A> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        &self.inner
+    }
+}
+
+/// Starts recording allocations, tagging them with 'tag'.
+/// Anything recorded by an earlier run is forgotten.
+pub fn start_tracking(tag: &'static str) {
+    let mut table = TABLE.lock();
+    table.clear();
+    table.tag = tag;
+    TRACKING.store(true, Ordering::Relaxed);
+}
+
+/// Changes the tag attached to the allocations made from now on.
+pub fn set_tag(tag: &'static str) {
+    TABLE.lock().tag = tag;
+}
+
+/// Stops recording and prints the allocations that are still alive over serial.
+/// Returns the number of leaked allocations, including those that didn't fit into the table.
+pub fn stop_tracking() -> usize {
+    TRACKING.store(false, Ordering::Relaxed);
+    let table = TABLE.lock();
+    for allocation in table.allocations() {
+        serial_println!(
+            "leaked {} bytes at {:#x} ({})",
+            allocation.size,
+            allocation.addr,
+            allocation.tag
+        );
+    }
+    if table.overflowed > 0 {
+        serial_println!("{} more allocations that didn't fit into the table were not freed", table.overflowed);
+    }
+    table.tracked + table.overflowed
+}
@@ -26,14 +26,39 @@ use x86_64::{
             SegmentSelector,
             GlobalDescriptorTable,
             Descriptor
-        }
+        },
+        paging::{
+            Page,
+            PageTableFlags,
+        },
+    },
+};
+
+use crate::memory::{
+    self,
+    vma::{
+        RegionKind,
+        VIRTUAL_REGIONS,
     },
 };
 
+use core::ops::Range;
+
 use lazy_static::lazy_static;
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 4;
 
+/// The double fault stack is mapped at this address, with an unmapped
+/// guard page right below and right above it
+pub const DOUBLE_FAULT_STACK_START: u64 = 0x_5555_5555_1000;
+const STACK_SIZE: u64 = 4096 * 5; // 20,480
+const PAGE_SIZE: u64 = 4096;
+
+/// The kernel stack the bootloader sets up, see [package.metadata.bootloader] in
+/// Cargo.toml. Its first page is the guard page, the stack's pages follow it.
+pub const KERNEL_STACK_START: u64 = 0x_FFFF_FF80_0000_0000;
+const KERNEL_STACK_PAGES: u64 = 128;
+
 lazy_static! {
     static ref GDT: ( GlobalDescriptorTable, Selectors ) = {
         let mut gdt = GlobalDescriptorTable::new();
@@ -52,19 +77,51 @@ lazy_static! {
     static ref TSS: TaskStateSegment = {
         let mut tss = TaskStateSegment::new();
         // tss.interrupt_stack_table[0] = ...
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5; // 20,480
-            // static mut STACK: [u8, 20,480]
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
-        };
+        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = map_double_fault_stack();
         tss
     };
 }
 
+// maps the double fault stack and returns its top, the stack grows down
+fn map_double_fault_stack() -> VirtAddr {
+    let stack_start = VirtAddr::new(DOUBLE_FAULT_STACK_START);
+    let stack_end = stack_start + STACK_SIZE;
+    let pages = Page::range(Page::containing_address(stack_start), Page::containing_address(stack_end));
+
+    VIRTUAL_REGIONS.lock()
+        .reserve(stack_start, STACK_SIZE, RegionKind::Stack, "double fault stack")
+        .expect("the double fault stack's virtual range is taken");
+    let mut kernel_memory = memory::KERNEL_MEMORY.lock();
+    let kernel_memory = kernel_memory.as_mut()
+        .expect("kernel memory must be initialized before the GDT");
+    memory::check_guard_pages(&kernel_memory.mapper, pages)
+        .expect("double fault stack guard pages are mapped");
+    kernel_memory.map_pages(pages, PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE)
+        .expect("mapping the double fault stack failed");
+    stack_end
+}
+
+/// Returns true if 'addr' lies in one of the guard pages around the double fault stack.
+pub fn is_double_fault_stack_guard(addr: VirtAddr) -> bool {
+    let addr = addr.as_u64();
+    let stack_end = DOUBLE_FAULT_STACK_START + STACK_SIZE;
+    (DOUBLE_FAULT_STACK_START - PAGE_SIZE..DOUBLE_FAULT_STACK_START).contains(&addr)
+        || (stack_end..stack_end + PAGE_SIZE).contains(&addr)
+}
+
+/// Returns the range of the kernel stack or the double fault stack 'addr' lies in.
+pub fn stack_containing(addr: VirtAddr) -> Option<Range<VirtAddr>> {
+    let kernel_stack = KERNEL_STACK_START + PAGE_SIZE..KERNEL_STACK_START + (KERNEL_STACK_PAGES + 1) * PAGE_SIZE;
+    let double_fault_stack = DOUBLE_FAULT_STACK_START..DOUBLE_FAULT_STACK_START + STACK_SIZE;
+    [kernel_stack, double_fault_stack]
+        .iter()
+        .find(|stack| stack.contains(&addr.as_u64()))
+        .map(|stack| VirtAddr::new(stack.start)..VirtAddr::new(stack.end))
+}
+
+/// Loads the GDT and the TSS.
+/// The double fault stack is mapped on the first call, so the kernel memory has to be
+/// handed over with `memory::init_kernel_memory` before.
 pub fn init() {
 
     GDT.0.load(); // loading the null segment selector
@@ -1,5 +1,7 @@
 use crate::{ 
+    allocator,
     gdt,
+    memory,
     println,
     print
 };
@@ -11,6 +13,7 @@ use x86_64::structures::idt::{
 };
 
 use x86_64::registers::control::Cr2;
+use x86_64::VirtAddr;
 use x86_64::instructions::port::Port; 
 
 
@@ -70,12 +73,29 @@ pub fn init_idt() {
     IDT.load();
 }
 
+/// Names the overflow a fault at 'addr' points to, if it hit one of the guard pages
+/// around the heap or the double fault stack.
+pub fn guard_page_fault(addr: VirtAddr) -> Option<&'static str> {
+    if allocator::is_heap_guard(addr) {
+        Some("heap overflow")
+    } else if gdt::is_double_fault_stack_guard(addr) {
+        Some("IST stack overflow")
+    } else {
+        None
+    }
+}
+
 // x86_64 arch does not allow returning from a double fault so
 // the exception handler should diverge ( -> !)
 // the _error_code is always 0
 extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame, _error_code: u64) -> !
 {
+    // running over the end of the double fault stack faults again while the fault
+    // is delivered, which ends up here with a fresh stack and the guard page in CR2
+    if let Some(overflow) = guard_page_fault(Cr2::read()) {
+        panic!("EXCEPTION: DOUBLE FAULT ({}) \n {:#?}", overflow, stack_frame);
+    }
     panic!("EXCEPTION: DOUBLE FAULT \n {:#?}", stack_frame);
 }
 
@@ -97,10 +117,39 @@ extern "x86-interrupt" fn page_fault_handler(
     error_code: PageFaultErrorCode
 ) 
 {
+    // a kernel mapping that was added while a user address space is active,
+    // its level 4 entry is still missing in the active table
+    if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+        && memory::address_space::sync_kernel_entry(Cr2::read())
+    {
+        return;
+    }
+    // a page of a demand paged region that is touched for the first time,
+    // back it and return to retry the access
+    if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+        && memory::map_on_demand(Cr2::read())
+    {
+        return;
+    }
+    // a write to a page shared copy-on-write, it gets its own copy of the frame
+    if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE)
+        && memory::resolve_copy_on_write(Cr2::read())
+    {
+        return;
+    }
+
     println!("**********************************************************");
     print!("a PAGE FAULT EXCEPTION occurred at ");
     println!("{:?}", Cr2::read());
     println!("Error Code: {:?}\n", error_code);
+    if let Some(overflow) = guard_page_fault(Cr2::read()) {
+        println!("guard page hit: {}", overflow);
+    }
+    if error_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH | PageFaultErrorCode::PROTECTION_VIOLATION) {
+        println!("tried to execute a no-execute page");
+    } else if error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE | PageFaultErrorCode::PROTECTION_VIOLATION) {
+        println!("tried to write to a read-only page");
+    }
     println!("**********************************************************");
     println!("Stack Frame:");
     println!("Instruction Pointer: {:?}", stack_frame.instruction_pointer);
@@ -6,6 +6,8 @@
 #![feature(custom_test_frameworks, abi_x86_interrupt, alloc_error_handler)]
 // allows use use mutable reference types in const functions at the moment; Support is yet unstable.
 #![feature(const_mut_refs)]
+// Arc::try_new, so spawning a task can fail instead of panicking when the heap is full
+#![feature(allocator_api)]
 #![test_runner(crate::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
@@ -73,8 +75,13 @@ where
     T: Fn(),
 {
     fn run(&self) {
-        serial_print!("{}...\t", core::any::type_name::<T>());
+        let name = core::any::type_name::<T>();
+        serial_print!("{}...\t", name);
+        // a test must free everything it allocates
+        allocator::tracking::start_tracking(name);
         self();
+        let leaks = allocator::tracking::stop_tracking();
+        assert_eq!(leaks, 0, "{} allocations leaked", leaks);
         serial_println!("[ok]");
     }
 }
@@ -119,5 +126,6 @@ pub fn hlt_loop() -> ! {
 
 #[alloc_error_handler]
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
+    allocator::oom::report(layout);
     panic!("allocation error: {:?}", layout)
 }
\ No newline at end of file
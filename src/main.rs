@@ -10,7 +10,7 @@ extern crate alloc;
 use core::panic::PanicInfo;
 use oubre_os::{
     memory,
-    memory::BootInfoFrameAllocator,
+    memory::bitmap::BitmapFrameAllocator,
     gdt, 
         interrupts, 
         println, 
@@ -25,7 +25,7 @@ use bootloader::{
 use x86_64::{
     instructions::interrupts as hardware_interrupts,
     VirtAddr,
-    structures::paging::Page,
+    PhysAddr,
 };
 
 use alloc::{
@@ -39,7 +39,10 @@ use alloc::{
 use oubre_os::task::{
     Task,
     //simple_executor::SimpleExecutor,
-    executor::Executor,
+    executor::{
+        Executor,
+        SpawnError,
+    },
     keyboard::print_keypresses,
 };
 
@@ -61,6 +64,9 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     Copy Left @ www.rasheedstarlet.com
     ");
 
+    // what the bootloader left for us
+    memory::report::print_memory_map(&boot_info.memory_map);
+
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
 
     let mut mapper = unsafe {
@@ -69,16 +75,25 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
 
     // let mut frame_allocator = EmptyFrameAllocator;
     let mut frame_allocator = unsafe {
-        BootInfoFrameAllocator::init(&boot_info.memory_map)
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
     };
 
-    // map an unused page
-    let page = Page::containing_address(VirtAddr::new(0x89980000));
-    memory::create_example_mapping(page, &mut mapper, &mut frame_allocator);
-
     allocator::init_heap(&mut mapper, &mut frame_allocator)
     .expect("heap initialization failed");
 
+    // from here on the heap maps more pages on its own when it runs out of memory
+    memory::init_kernel_memory(mapper, frame_allocator);
+    // no page of the kernel is writable and executable from here on
+    memory::protection::protect_kernel(&boot_info.memory_map)
+        .expect("protecting the kernel failed");
+
+    // map the VGA text buffer a second time, it is unmapped again when dropped
+    let vga_buffer = unsafe {
+        memory::mmio::map_mmio_with(PhysAddr::new(0xb8000), 80 * 25 * 2, memory::mmio::CacheMode::WriteThrough)
+    }.expect("mapping the VGA buffer failed");
+    println!("VGA buffer mapped at {:?}", vga_buffer.base());
+    core::mem::drop(vga_buffer);
+
     // allocating a number on the heap
     let heap_num = Box::new(41);
     println!("heap number at {:p}", heap_num);
@@ -98,6 +113,7 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     println!("current reference count is {}", Rc::strong_count(&cloned_ref2));
     core::mem::drop(ref_counted);
     println!("current reference count is {} now", Rc::strong_count(&cloned_ref));
+    memory::vma::print_layout();
 
 
     
@@ -123,8 +139,11 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     // MULTITASKING
     //let mut executor = SimpleExecutor::new();
     let mut executor = Executor::new();
-    executor.spawn(Task::new(example_task()));
-    executor.spawn(Task::new(print_keypresses()));
+    for task in [Task::try_new(example_task()), Task::try_new(print_keypresses())] {
+        if let Err(err) = task.map_err(SpawnError::from).and_then(|task| executor.try_spawn(task)) {
+            println!("spawning a task failed: {:?}", err);
+        }
+    }
     executor.run();
     
     async fn async_number() -> u32 {
@@ -1,3 +1,21 @@
+/// Frame allocators
+pub mod bitmap;
+pub mod buddy;
+/// Virtual address space regions
+pub mod vma;
+/// Page table inspection
+pub mod inspect;
+/// Copy-on-write sharing of frames
+pub mod cow;
+/// Physical memory map report
+pub mod report;
+/// Device memory
+pub mod mmio;
+/// Page tables of user programs
+pub mod address_space;
+/// No-execute and write protection
+pub mod protection;
+
 use x86_64::{
     structures::paging::{
         Page,
@@ -8,7 +26,19 @@ use x86_64::{
         PhysFrame,
         Mapper,
         Size4KiB,
+        Size2MiB,
+        Size1GiB,
+        PageSize,
         FrameAllocator,
+        FrameDeallocator,
+        Translate,
+        mapper::{
+            MappedFrame,
+            MapToError,
+            TranslateResult,
+            UnmapError,
+        },
+        page::PageRange,
     },
     registers::control::Cr3,
     VirtAddr,
@@ -20,10 +50,334 @@ use bootloader::bootinfo::{
     MemoryRegionType,
 };
 
+use spin::Mutex;
+
+use bitmap::BitmapFrameAllocator;
+
+/// The page tables and frame allocator of the running kernel.
+///
+/// Empty until they are handed over with `init_kernel_memory`. Code that has to map
+/// memory on its own, like the heap when it grows, locks it to do so.
+/// Must not be locked while allocating on the heap, the heap locks it when it grows.
+pub static KERNEL_MEMORY: Mutex<Option<KernelMemory>> = Mutex::new(None);
+
+pub struct KernelMemory {
+    pub mapper: OffsetPageTable<'static>,
+    pub frame_allocator: BitmapFrameAllocator,
+}
+
+impl KernelMemory {
+    /// Backs each page of the given range with a fresh frame.
+    ///
+    /// If mapping fails halfway, the pages mapped so far are unmapped again
+    /// and their frames freed.
+    pub fn map_pages(&mut self, pages: PageRange, flags: Flags) -> Result<(), MapToError<Size4KiB>> {
+        for page in pages {
+            let result = match self.frame_allocator.allocate_frame() {
+                Some(frame) => unsafe {
+                    self.mapper.map_to(page, frame, flags, &mut self.frame_allocator)
+                },
+                None => Err(MapToError::FrameAllocationFailed),
+            };
+            match result {
+                Ok(flush) => flush.flush(),
+                Err(err) => {
+                    self.unmap_pages(Page::range(pages.start, page))
+                        .expect("unmapping freshly mapped pages failed");
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Unmaps each page of the given range and frees the frames behind them,
+    /// frames shared copy-on-write are freed once their last mapping is gone.
+    ///
+    /// The caller must make sure that no references into the pages are left
+    /// and that the frames were not handed out by another allocator.
+    pub fn unmap_pages(&mut self, pages: PageRange) -> Result<(), UnmapError> {
+        for page in pages {
+            let (frame, flush) = self.mapper.unmap(page)?;
+            flush.flush();
+            unsafe { self.release_frame(frame) };
+        }
+        Ok(())
+    }
+
+    /// Backs 'size' bytes at 'start' with fresh frames. Where 'start' is aligned to
+    /// a 1GiB or 2MiB page and enough contiguous frames are free, huge pages are used,
+    /// 4KiB pages for the rest. Fewer, larger pages take less room in the TLB.
+    ///
+    /// 'start' and 'size' must be 4KiB aligned. If mapping fails halfway, the pages
+    /// mapped so far are unmapped again and their frames freed.
+    pub fn map_range(&mut self, start: VirtAddr, size: u64, flags: Flags) -> Result<(), MapToError<Size4KiB>> {
+        let huge_1gib = has_1gib_pages();
+        let mut offset = 0;
+        while offset < size {
+            let addr = start + offset;
+            match self.map_largest_fresh(addr, size - offset, flags, huge_1gib) {
+                Ok(page_size) => offset += page_size,
+                Err(err) => {
+                    self.unmap_range(start, offset)
+                        .expect("unmapping freshly mapped pages failed");
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps 'size' bytes at 'start' to the physical memory at 'phys', with the
+    /// largest pages both addresses are aligned to. Used for memory that already
+    /// exists, like device memory.
+    ///
+    /// This function is unsafe because the caller must make sure that mapping the
+    /// physical range does not break memory safety, e.g. by aliasing frames in use.
+    pub unsafe fn map_physical_range(&mut self, start: VirtAddr, phys: PhysAddr, size: u64, flags: Flags)
+    -> Result<(), MapToError<Size4KiB>>
+    {
+        let huge_1gib = has_1gib_pages();
+        let mut offset = 0;
+        while offset < size {
+            match self.map_largest_physical(start + offset, phys + offset, size - offset, flags, huge_1gib) {
+                Ok(page_size) => offset += page_size,
+                Err(err) => {
+                    self.unmap_physical_range(start, offset)
+                        .expect("unmapping freshly mapped pages failed");
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Unmaps the pages of any size in the 'size' bytes at 'start' and frees their frames.
+    /// Fails with ParentEntryHugePage if a huge page reaches out of the range.
+    ///
+    /// The caller must make sure that no references into the pages are left
+    /// and that the frames were not handed out by another allocator.
+    pub fn unmap_range(&mut self, start: VirtAddr, size: u64) -> Result<(), UnmapError> {
+        self.unmap_range_with(start, size, true)
+    }
+
+    /// Unmaps a range mapped with `map_physical_range`, the frames are not freed.
+    pub fn unmap_physical_range(&mut self, start: VirtAddr, size: u64) -> Result<(), UnmapError> {
+        self.unmap_range_with(start, size, false)
+    }
+
+    fn unmap_range_with(&mut self, start: VirtAddr, size: u64, free: bool) -> Result<(), UnmapError> {
+        let mut offset = 0;
+        while offset < size {
+            let addr = start + offset;
+            let page_size = match self.mapper.translate(addr) {
+                TranslateResult::Mapped { frame: MappedFrame::Size4KiB(_), .. } => Size4KiB::SIZE,
+                TranslateResult::Mapped { frame: MappedFrame::Size2MiB(_), .. } => Size2MiB::SIZE,
+                TranslateResult::Mapped { frame: MappedFrame::Size1GiB(_), .. } => Size1GiB::SIZE,
+                _ => return Err(UnmapError::PageNotMapped),
+            };
+            if !addr.is_aligned(page_size) || size - offset < page_size {
+                return Err(UnmapError::ParentEntryHugePage);
+            }
+            match page_size {
+                Size4KiB::SIZE => self.unmap_page::<Size4KiB>(addr, free)?,
+                Size2MiB::SIZE => self.unmap_page::<Size2MiB>(addr, free)?,
+                _ => self.unmap_page::<Size1GiB>(addr, free)?,
+            }
+            offset += page_size;
+        }
+        Ok(())
+    }
+
+    fn unmap_page<S: PageSize>(&mut self, addr: VirtAddr, free: bool) -> Result<(), UnmapError>
+    where
+        OffsetPageTable<'static>: Mapper<S>,
+    {
+        let (frame, flush) = self.mapper.unmap(Page::<S>::containing_address(addr))?;
+        flush.flush();
+        if free && S::SIZE == Size4KiB::SIZE {
+            // only 4KiB frames can be shared
+            unsafe { self.release_frame(PhysFrame::containing_address(frame.start_address())) };
+        } else if free {
+            unsafe { self.frame_allocator.deallocate_frame_of(frame) };
+        }
+        Ok(())
+    }
+
+    // maps the largest page that fits at 'addr' to a fresh frame, returns its size
+    fn map_largest_fresh(&mut self, addr: VirtAddr, remaining: u64, flags: Flags, huge_1gib: bool)
+    -> Result<u64, MapToError<Size4KiB>>
+    {
+        if huge_1gib && fits::<Size1GiB>(addr, remaining) && self.map_fresh::<Size1GiB>(addr, flags)? {
+            return Ok(Size1GiB::SIZE);
+        }
+        if fits::<Size2MiB>(addr, remaining) && self.map_fresh::<Size2MiB>(addr, flags)? {
+            return Ok(Size2MiB::SIZE);
+        }
+        if self.map_fresh::<Size4KiB>(addr, flags)? {
+            return Ok(Size4KiB::SIZE);
+        }
+        Err(MapToError::FrameAllocationFailed)
+    }
+
+    // maps the largest page that fits at 'addr' to 'phys', returns its size
+    unsafe fn map_largest_physical(&mut self, addr: VirtAddr, phys: PhysAddr, remaining: u64, flags: Flags, huge_1gib: bool)
+    -> Result<u64, MapToError<Size4KiB>>
+    {
+        if huge_1gib && fits::<Size1GiB>(addr, remaining) && phys.is_aligned(Size1GiB::SIZE)
+            && self.map_frame(addr, PhysFrame::<Size1GiB>::containing_address(phys), flags)?
+        {
+            return Ok(Size1GiB::SIZE);
+        }
+        if fits::<Size2MiB>(addr, remaining) && phys.is_aligned(Size2MiB::SIZE)
+            && self.map_frame(addr, PhysFrame::<Size2MiB>::containing_address(phys), flags)?
+        {
+            return Ok(Size2MiB::SIZE);
+        }
+        self.map_frame(addr, PhysFrame::<Size4KiB>::containing_address(phys), flags)?;
+        Ok(Size4KiB::SIZE)
+    }
+
+    // maps a page of size S at 'addr' to a fresh frame, Ok(false) if the page can't be used
+    fn map_fresh<S: PageSize>(&mut self, addr: VirtAddr, flags: Flags) -> Result<bool, MapToError<Size4KiB>>
+    where
+        OffsetPageTable<'static>: Mapper<S>,
+    {
+        let frame = match self.frame_allocator.allocate_frame_of::<S>() {
+            Some(frame) => frame,
+            None => return Ok(false),
+        };
+        let mapped = unsafe { self.map_frame(addr, frame, flags) };
+        if !matches!(mapped, Ok(true)) {
+            unsafe { self.frame_allocator.deallocate_frame_of(frame) };
+        }
+        mapped
+    }
+
+    // maps a page of size S at 'addr' to 'frame', Ok(false) if a huge page can't go there
+    // because a page table for smaller pages is in the way
+    unsafe fn map_frame<S: PageSize>(&mut self, addr: VirtAddr, frame: PhysFrame<S>, flags: Flags)
+    -> Result<bool, MapToError<Size4KiB>>
+    where
+        OffsetPageTable<'static>: Mapper<S>,
+    {
+        let page = Page::<S>::containing_address(addr);
+        match self.mapper.map_to(page, frame, flags, &mut self.frame_allocator) {
+            Ok(flush) => {
+                flush.flush();
+                Ok(true)
+            }
+            Err(MapToError::PageAlreadyMapped(_)) if S::SIZE != Size4KiB::SIZE => Ok(false),
+            Err(err) => Err(as_4kib_error(err)),
+        }
+    }
+}
+
+/// Returns true if the CPU can map 1GiB pages, 2MiB pages are always there in long mode.
+// __cpuid is only unsafe on older toolchains
+#[allow(unused_unsafe)]
+pub fn has_1gib_pages() -> bool {
+    // the Page1GB bit of the extended feature flags
+    unsafe { core::arch::x86_64::__cpuid(0x8000_0001).edx & (1 << 26) != 0 }
+}
+
+// true if a page of size S starts at 'addr' and fits into 'remaining' bytes
+fn fits<S: PageSize>(addr: VirtAddr, remaining: u64) -> bool {
+    addr.is_aligned(S::SIZE) && remaining >= S::SIZE
+}
+
+fn as_4kib_error<S: PageSize>(err: MapToError<S>) -> MapToError<Size4KiB> {
+    match err {
+        MapToError::FrameAllocationFailed => MapToError::FrameAllocationFailed,
+        MapToError::ParentEntryHugePage => MapToError::ParentEntryHugePage,
+        MapToError::PageAlreadyMapped(frame) => {
+            MapToError::PageAlreadyMapped(PhysFrame::containing_address(frame.start_address()))
+        }
+    }
+}
+
+/// Makes sure the pages right in front of and behind the given range are unmapped,
+/// so they can serve as guard pages. Returns PageAlreadyMapped for a mapped one.
+pub fn check_guard_pages<M: Translate>(mapper: &M, pages: PageRange) -> Result<(), MapToError<Size4KiB>> {
+    for guard in [pages.start - 1, pages.end].iter() {
+        // also catches pages that are part of a huge page
+        if let Some(addr) = mapper.translate_addr(guard.start_address()) {
+            return Err(MapToError::PageAlreadyMapped(PhysFrame::containing_address(addr)));
+        }
+    }
+    Ok(())
+}
+
+/// Backs the page 'addr' lies in with a zeroed frame if it belongs to a demand paged
+/// region, called by the page fault handler for faults on non present pages.
+///
+/// Returns false if the address is outside of any demand paged region, or if the
+/// page could not be mapped, then the fault is a real one. The locks are only tried,
+/// a fault while they are held must not deadlock the handler.
+///
+/// Only kernel regions are backed here, the kernel's page table is not the one
+/// user programs run on, their pages are mapped through their `AddressSpace`.
+pub fn map_on_demand(addr: VirtAddr) -> bool {
+    match vma::VIRTUAL_REGIONS.try_lock().and_then(|regions| regions.find(addr)) {
+        Some(region) if region.demand_paged && region.kind != vma::RegionKind::User => {}
+        _ => return false,
+    }
+    let mut kernel_memory = match KERNEL_MEMORY.try_lock() {
+        Some(kernel_memory) => kernel_memory,
+        None => return false,
+    };
+    let kernel_memory = match kernel_memory.as_mut() {
+        Some(kernel_memory) => kernel_memory,
+        None => return false,
+    };
+
+    let page: Page<Size4KiB> = Page::containing_address(addr);
+    let flags = Flags::PRESENT | Flags::WRITABLE | Flags::NO_EXECUTE;
+    if kernel_memory.map_pages(Page::range(page, page + 1), flags).is_err() {
+        return false;
+    }
+    // the frame may still hold data of whoever used it before
+    unsafe {
+        core::ptr::write_bytes(page.start_address().as_mut_ptr::<u8>(), 0, Size4KiB::SIZE as usize);
+    }
+    true
+}
+
+/// Gives the copy-on-write page 'addr' lies in its own frame, called by the page fault
+/// handler for writes to present pages. Returns false if it is not a copy-on-write page,
+/// then the fault is a real one.
+pub fn resolve_copy_on_write(addr: VirtAddr) -> bool {
+    match KERNEL_MEMORY.try_lock() {
+        Some(mut kernel_memory) => match kernel_memory.as_mut() {
+            Some(kernel_memory) => kernel_memory.resolve_copy_on_write(addr),
+            None => false,
+        },
+        None => false,
+    }
+}
+
+/// Hands the page tables and frame allocator over to KERNEL_MEMORY.
+pub fn init_kernel_memory(mut mapper: OffsetPageTable<'static>, frame_allocator: BitmapFrameAllocator) {
+    address_space::set_kernel_table(&mut mapper);
+    KERNEL_MEMORY.lock().replace(KernelMemory {
+        mapper,
+        frame_allocator,
+    });
+}
+
 /// A FrameAllocator that returns usable frames from the bootloader's memory map.
+///
+/// Instead of re-walking the memory map on every allocation, the allocator
+/// keeps a cursor into it: the index of the region currently being carved up
+/// and the address of the next frame to hand out in that region. Each call
+/// only moves the cursor forward, so allocation runs in constant time
+/// (apart from skipping over non-usable regions, which happens once per region).
 pub struct BootInfoFrameAllocator {
     memory_map: &'static MemoryMap,
-    next: usize,
+    // index of the memory region the cursor is in
+    region: usize,
+    // start address of the next frame to hand out
+    next: u64,
 }
 
 impl BootInfoFrameAllocator {
@@ -35,51 +389,27 @@ impl BootInfoFrameAllocator {
     pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
         BootInfoFrameAllocator {
             memory_map,
+            region: 0,
             next: 0,
         }
     }
-
-    /// Returns an iterator over th usable frames specified in the memory map.
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        // get usable regions from the memory map
-        let regions = self.memory_map.iter();
-        let usable_regions = regions.filter(
-            |r| r.region_type == MemoryRegionType::Usable
-        );
-        // map each region to its address range
-        let addr_ranges = usable_regions.map(
-            |r| r.range.start_addr()..r.range.end_addr()
-        );
-        // transform to an iterator of frame start addresses
-        let frame_addresses = addr_ranges.flat_map(
-            |r| r.step_by(4096)
-        );
-        // create 'PhysFrame' types from th start addresses
-        frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
-    }
 }
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        frame
-    }
-}
-
-
-pub fn create_example_mapping<T: FrameAllocator<Size4KiB>> (
-    page: Page,
-    mapper: &mut OffsetPageTable,
-    frame_allocator: &mut T,
-) {
-    let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
-    let flags = Flags::PRESENT | Flags::WRITABLE; 
-
-    // unsafe because the caller must ensure that the frame is not already in use
-    // mapping a frame twice could lead to UB
-    unsafe {
-        mapper.map_to(page, frame, flags, frame_allocator).expect("map_to failed").flush();
+        while let Some(region) = self.memory_map.get(self.region) {
+            if region.region_type == MemoryRegionType::Usable {
+                // the cursor may still point into a previous region
+                let start = self.next.max(region.range.start_addr());
+                if start + Size4KiB::SIZE <= region.range.end_addr() {
+                    self.next = start + Size4KiB::SIZE;
+                    return Some(PhysFrame::containing_address(PhysAddr::new(start)));
+                }
+            }
+            // region exhausted or not usable, move the cursor to the next one
+            self.region += 1;
+        }
+        None
     }
 }
 
@@ -93,9 +423,14 @@ unsafe impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
     }
 }
 
+/// Turns on no-execute and write protection and returns the active page tables.
+///
+/// This function is unsafe because the caller must guarantee that all of physical
+/// memory is mapped at 'physical_mem_offset'.
 pub unsafe fn init(physical_mem_offset: VirtAddr) 
 -> OffsetPageTable<'static> 
 {
+    protection::enable();
     let level_4_table = active_level_4_table(physical_mem_offset);
     OffsetPageTable::new(level_4_table, physical_mem_offset)
 }
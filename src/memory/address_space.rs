@@ -0,0 +1,231 @@
+use core::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{
+        mapper::MapToError,
+        page::PageRange,
+        page_table::PageTableEntry,
+        FrameAllocator,
+        FrameDeallocator,
+        Mapper,
+        OffsetPageTable,
+        PageTable,
+        PageTableFlags as Flags,
+        PhysFrame,
+        Size1GiB,
+        Size2MiB,
+        Size4KiB,
+    },
+    PhysAddr,
+    VirtAddr,
+};
+
+use super::{
+    KernelMemory,
+    KERNEL_MEMORY,
+};
+
+/// The part of every address space that belongs to the user program. The kernel lives
+/// in the level 4 entries around it, which all address spaces share.
+pub const USER_START: u64 = 0x_0000_1000_0000_0000;
+pub const USER_END: u64 = 0x_0000_4000_0000_0000;
+
+// the level 4 entries of the user part
+const USER_ENTRIES: core::ops::Range<usize> = (USER_START >> 39) as usize..(USER_END >> 39) as usize;
+
+// where the kernel's level 4 table and all of physical memory are mapped, 0 before
+// `init_kernel_memory`, so the page fault handler can sync entries without locking
+static KERNEL_LEVEL_4_TABLE: AtomicU64 = AtomicU64::new(0);
+static PHYS_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// A set of page tables with its own user part and the kernel mapped around it.
+///
+/// The level 4 entries of the kernel point to the kernel's own tables, so kernel
+/// mappings below them show up in every address space. Level 4 entries the kernel
+/// adds later are copied over each time the address space is activated, and by the
+/// page fault handler if the kernel touches them while the address space is active.
+/// Dropping it frees every frame of the user part, including the page tables.
+pub struct AddressSpace {
+    mapper: OffsetPageTable<'static>,
+    level_4_frame: PhysFrame,
+}
+
+impl AddressSpace {
+    /// Creates an address space with an empty user part.
+    /// Fails if no frame is left for the level 4 table.
+    pub fn new() -> Result<Self, MapToError<Size4KiB>> {
+        let mut kernel_memory = KERNEL_MEMORY.lock();
+        let kernel_memory = kernel_memory.as_mut().expect("kernel memory not initialized");
+        let level_4_frame = kernel_memory.frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+
+        let phys_offset = kernel_memory.mapper.phys_offset();
+        let mut mapper = unsafe {
+            let table = &mut *(phys_offset + level_4_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
+            table.zero();
+            OffsetPageTable::new(table, phys_offset)
+        };
+        copy_kernel_entries(kernel_memory, mapper.level_4_table());
+        Ok(AddressSpace { mapper, level_4_frame })
+    }
+
+    /// The page tables of the address space, e.g. to map pages into the user part.
+    /// New page tables have to come from the kernel's frame allocator.
+    pub fn mapper(&mut self) -> &mut OffsetPageTable<'static> {
+        &mut self.mapper
+    }
+
+    pub fn level_4_frame(&self) -> PhysFrame {
+        self.level_4_frame
+    }
+
+    /// Returns true if this is the address space the CPU currently uses.
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0 == self.level_4_frame
+    }
+
+    /// Backs the pages with fresh frames that user mode can access.
+    /// The pages mapped before a failure stay mapped until the address space is dropped.
+    ///
+    /// Panics if the pages are not in the user part.
+    pub fn map_user_pages(&mut self, pages: PageRange, flags: Flags) -> Result<(), MapToError<Size4KiB>> {
+        assert!(
+            pages.start.start_address().as_u64() >= USER_START && pages.end.start_address().as_u64() <= USER_END,
+            "pages outside of the user part"
+        );
+        let flags = flags | Flags::PRESENT | Flags::USER_ACCESSIBLE;
+        let active = self.is_active();
+        let mut kernel_memory = KERNEL_MEMORY.lock();
+        let kernel_memory = kernel_memory.as_mut().expect("kernel memory not initialized");
+        for page in pages {
+            let frame = kernel_memory.frame_allocator
+                .allocate_frame()
+                .ok_or(MapToError::FrameAllocationFailed)?;
+            let flush = unsafe {
+                self.mapper.map_to(page, frame, flags, &mut kernel_memory.frame_allocator)
+            };
+            match flush {
+                Ok(flush) if active => flush.flush(),
+                // the TLB has no entries of an inactive address space
+                Ok(flush) => flush.ignore(),
+                Err(err) => {
+                    unsafe { kernel_memory.frame_allocator.deallocate_frame(frame) };
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Switches the CPU to this address space.
+    ///
+    /// This function is unsafe because references into the user part of the
+    /// previous address space become invalid.
+    pub unsafe fn activate(&mut self) {
+        let mut kernel_memory = KERNEL_MEMORY.lock();
+        let kernel_memory = kernel_memory.as_mut().expect("kernel memory not initialized");
+        copy_kernel_entries(kernel_memory, self.mapper.level_4_table());
+        let (_, flags) = Cr3::read();
+        Cr3::write(self.level_4_frame, flags);
+    }
+}
+
+/// Switches the CPU back to the kernel's own page tables, which have no user part.
+///
+/// This function is unsafe because references into the user part of the
+/// previous address space become invalid.
+pub unsafe fn activate_kernel() {
+    let mut kernel_memory = KERNEL_MEMORY.lock();
+    let mapper = &mut kernel_memory.as_mut().expect("kernel memory not initialized").mapper;
+    let phys_offset = mapper.phys_offset();
+    let table = VirtAddr::from_ptr(mapper.level_4_table());
+    let frame = PhysFrame::containing_address(PhysAddr::new(table.as_u64() - phys_offset.as_u64()));
+    let (_, flags) = Cr3::read();
+    Cr3::write(frame, flags);
+}
+
+/// Copies the kernel's level 4 entry for 'addr' into the active address space if it
+/// is missing there, called by the page fault handler for faults on non present pages.
+/// The kernel may have added the entry after the address space was activated.
+///
+/// Returns false if there was nothing to copy, then the fault is a real one. Nothing is
+/// locked, the fault may happen while the kernel memory is locked.
+pub fn sync_kernel_entry(addr: VirtAddr) -> bool {
+    let kernel_table = KERNEL_LEVEL_4_TABLE.load(Ordering::Acquire);
+    let index = usize::from(addr.p4_index());
+    if kernel_table == 0 || USER_ENTRIES.contains(&index) {
+        return false;
+    }
+    let phys_offset = PHYS_OFFSET.load(Ordering::Acquire);
+    let active_table = phys_offset + Cr3::read().0.start_address().as_u64();
+    if active_table == kernel_table {
+        return false;
+    }
+    unsafe {
+        let entry = &(&*(kernel_table as *const PageTable))[index];
+        let active_entry = &mut (&mut *(active_table as *mut PageTable))[index];
+        if entry.is_unused() || !active_entry.is_unused() {
+            return false;
+        }
+        *active_entry = entry.clone();
+    }
+    true
+}
+
+// remembers the kernel's level 4 table for `sync_kernel_entry`
+pub(super) fn set_kernel_table(mapper: &mut OffsetPageTable<'static>) {
+    let phys_offset = mapper.phys_offset();
+    PHYS_OFFSET.store(phys_offset.as_u64(), Ordering::Release);
+    KERNEL_LEVEL_4_TABLE.store(VirtAddr::from_ptr(mapper.level_4_table()).as_u64(), Ordering::Release);
+}
+
+// points the kernel's level 4 entries of 'table' to the kernel's tables
+fn copy_kernel_entries(kernel_memory: &mut KernelMemory, table: &mut PageTable) {
+    let kernel_table = kernel_memory.mapper.level_4_table();
+    for (index, entry) in kernel_table.iter().enumerate() {
+        if USER_ENTRIES.contains(&index) {
+            assert!(entry.is_unused(), "the kernel has mappings in the user part");
+        } else {
+            table[index] = entry.clone();
+        }
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        assert!(!self.is_active(), "dropping the active address space");
+        let mut kernel_memory = KERNEL_MEMORY.lock();
+        let kernel_memory = kernel_memory.as_mut().expect("kernel memory not initialized");
+        let phys_offset = self.mapper.phys_offset();
+        let table = self.mapper.level_4_table();
+        for index in USER_ENTRIES {
+            if table[index].flags().contains(Flags::PRESENT) {
+                unsafe { free_table(kernel_memory, phys_offset, &table[index], 3) };
+            }
+        }
+        unsafe { kernel_memory.frame_allocator.deallocate_frame(self.level_4_frame) };
+    }
+}
+
+// frees the frames mapped by the table of 'level' that 'entry' points to,
+// the tables below it and the table itself
+unsafe fn free_table(kernel_memory: &mut KernelMemory, phys_offset: VirtAddr, entry: &PageTableEntry, level: u8) {
+    let table = &*(phys_offset + entry.addr().as_u64()).as_ptr::<PageTable>();
+    for entry in table.iter().filter(|entry| entry.flags().contains(Flags::PRESENT)) {
+        if level == 1 {
+            kernel_memory.release_frame(PhysFrame::containing_address(entry.addr()));
+        } else if entry.flags().contains(Flags::HUGE_PAGE) && level == 2 {
+            kernel_memory.frame_allocator.deallocate_frame_of(PhysFrame::<Size2MiB>::containing_address(entry.addr()));
+        } else if entry.flags().contains(Flags::HUGE_PAGE) {
+            kernel_memory.frame_allocator.deallocate_frame_of(PhysFrame::<Size1GiB>::containing_address(entry.addr()));
+        } else {
+            free_table(kernel_memory, phys_offset, entry, level - 1);
+        }
+    }
+    kernel_memory.frame_allocator.deallocate_frame(PhysFrame::containing_address(entry.addr()));
+}
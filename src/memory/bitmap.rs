@@ -0,0 +1,211 @@
+use x86_64::{
+    structures::paging::{
+        PhysFrame,
+        PageSize,
+        Size4KiB,
+        FrameAllocator,
+        FrameDeallocator,
+    },
+    VirtAddr,
+    PhysAddr,
+};
+
+use bootloader::bootinfo::{
+    MemoryMap,
+    MemoryRegionType,
+};
+
+use core::{
+    ops::Range,
+    slice,
+};
+
+use super::report;
+
+// number of frames tracked by one word of the bitmap
+const FRAMES_PER_WORD: usize = 64;
+
+/// A FrameAllocator that tracks every usable frame in a bitmap, so frames can be
+/// handed back with `deallocate_frame` and reused later.
+///
+/// The bitmap holds one bit per 4KiB frame up to the highest usable frame of the
+/// memory map, a set bit meaning the frame is in use. It lives in the first usable
+/// region big enough to hold it and is accessed through the physical memory mapping
+/// the bootloader sets up, so no heap is needed.
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    // the usable regions, only their frames may be handed back
+    memory_map: &'static MemoryMap,
+    // the frames holding the bitmap itself, they are never handed out
+    bitmap_frames: Range<usize>,
+    // index of the first word that may still contain a free frame
+    next_free: usize,
+    free_frames: usize,
+}
+
+impl BitmapFrameAllocator {
+    /// Creates a BitmapFrameAllocator from the passed memory map.
+    ///
+    /// This function is unsafe because the caller must guarantee that the memory map
+    /// passed is valid, that all frames marked as 'USABLE' in it are really unused and
+    /// that the complete physical memory is mapped at 'physical_mem_offset'.
+    /// It must not be used together with another allocator over the same memory map.
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_mem_offset: VirtAddr) -> Self {
+        let usable_regions = || memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable);
+
+        // size the bitmap for all frames up to the highest usable one
+        let frame_count = usable_regions()
+            .map(|r| r.range.end_frame_number)
+            .max()
+            .unwrap_or(0) as usize;
+        let words = frame_count.div_ceil(FRAMES_PER_WORD);
+        let bitmap_bytes = words * core::mem::size_of::<u64>();
+        let bitmap_frames = (bitmap_bytes as u64).div_ceil(Size4KiB::SIZE);
+
+        // steal the frames for the bitmap itself from the first region that fits it
+        let bitmap_start = usable_regions()
+            .find(|r| r.range.end_frame_number - r.range.start_frame_number >= bitmap_frames)
+            .expect("no usable region large enough for the frame bitmap")
+            .range
+            .start_addr();
+        let bitmap_ptr: *mut u64 = (physical_mem_offset + bitmap_start).as_mut_ptr();
+        let bitmap = slice::from_raw_parts_mut(bitmap_ptr, words);
+
+        // everything is in use until the memory map says otherwise
+        for word in bitmap.iter_mut() {
+            *word = !0;
+        }
+        let bitmap_start_frame = (bitmap_start / Size4KiB::SIZE) as usize;
+        let mut allocator = BitmapFrameAllocator {
+            bitmap,
+            memory_map,
+            bitmap_frames: bitmap_start_frame..bitmap_start_frame + bitmap_frames as usize,
+            next_free: 0,
+            free_frames: 0,
+        };
+        for region in usable_regions() {
+            for frame in region.range.start_frame_number..region.range.end_frame_number {
+                allocator.clear(frame as usize);
+            }
+        }
+        for frame in allocator.bitmap_frames.clone() {
+            allocator.set(frame);
+        }
+        allocator
+    }
+
+    /// Returns the number of frames that are currently free.
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    /// Returns true if the given frame is tracked by the allocator and currently free.
+    pub fn is_free(&self, frame: PhysFrame) -> bool {
+        let index = Self::frame_index(frame);
+        match self.bitmap.get(index / FRAMES_PER_WORD) {
+            Some(word) => word & (1 << (index % FRAMES_PER_WORD)) == 0,
+            None => false,
+        }
+    }
+
+    /// Returns true if the frame can be handed out at all, it lies in a usable region
+    /// and doesn't hold the bitmap. All other frames are in use for good.
+    pub fn is_allocatable(&self, frame: PhysFrame) -> bool {
+        report::is_usable(self.memory_map, frame) && !self.bitmap_frames.contains(&Self::frame_index(frame))
+    }
+
+    /// Allocates a frame of page size S, for 2MiB and 1GiB pages a run of contiguous
+    /// 4KiB frames aligned to the page size. Returns None if there is no such run.
+    pub fn allocate_frame_of<S: PageSize>(&mut self) -> Option<PhysFrame<S>> {
+        let count = (S::SIZE / Size4KiB::SIZE) as usize;
+        let index = if count < FRAMES_PER_WORD {
+            Self::frame_index(self.allocate_frame()?)
+        } else {
+            self.allocate_words(count / FRAMES_PER_WORD)?
+        };
+        PhysFrame::from_start_address(PhysAddr::new(index as u64 * Size4KiB::SIZE)).ok()
+    }
+
+    /// Frees a frame returned by `allocate_frame_of`.
+    ///
+    /// This function is unsafe because the caller must ensure that the frame is unused.
+    pub unsafe fn deallocate_frame_of<S: PageSize>(&mut self, frame: PhysFrame<S>) {
+        let first = PhysFrame::containing_address(frame.start_address());
+        for frame in PhysFrame::range(first, first + S::SIZE / Size4KiB::SIZE) {
+            self.deallocate_frame(frame);
+        }
+    }
+
+    // finds 'words' words of free frames in a row, aligned to their count,
+    // marks them as used and returns the index of the first frame
+    fn allocate_words(&mut self, words: usize) -> Option<usize> {
+        // words before next_free are known to be full
+        let mut start = self.next_free / words * words;
+        while start + words <= self.bitmap.len() {
+            let run = &mut self.bitmap[start..start + words];
+            if run.iter().all(|&word| word == 0) {
+                for word in run.iter_mut() {
+                    *word = !0;
+                }
+                self.free_frames -= words * FRAMES_PER_WORD;
+                return Some(start * FRAMES_PER_WORD);
+            }
+            start += words;
+        }
+        None
+    }
+
+    fn frame_index(frame: PhysFrame) -> usize {
+        (frame.start_address().as_u64() / Size4KiB::SIZE) as usize
+    }
+
+    // marks the frame with the given index as used
+    fn set(&mut self, index: usize) {
+        let word = &mut self.bitmap[index / FRAMES_PER_WORD];
+        let mask = 1 << (index % FRAMES_PER_WORD);
+        if *word & mask == 0 {
+            *word |= mask;
+            self.free_frames -= 1;
+        }
+    }
+
+    // marks the frame with the given index as free
+    fn clear(&mut self, index: usize) {
+        let word = &mut self.bitmap[index / FRAMES_PER_WORD];
+        let mask = 1 << (index % FRAMES_PER_WORD);
+        if *word & mask != 0 {
+            *word &= !mask;
+            self.free_frames += 1;
+        }
+        self.next_free = self.next_free.min(index / FRAMES_PER_WORD);
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        // words before next_free are known to be full, so the search starts there
+        while let Some(&word) = self.bitmap.get(self.next_free) {
+            if word != !0 {
+                let index = self.next_free * FRAMES_PER_WORD + (!word).trailing_zeros() as usize;
+                self.set(index);
+                let addr = PhysAddr::new(index as u64 * Size4KiB::SIZE);
+                return Some(PhysFrame::containing_address(addr));
+            }
+            self.next_free += 1;
+        }
+        None
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        // the kernel image, page tables and device memory are marked used as well,
+        // but they never came from this allocator
+        assert!(self.is_allocatable(frame), "deallocating frame {:?} that is not usable memory", frame);
+        // freeing a frame that is not in use points to a double free
+        assert!(!self.is_free(frame), "deallocating frame {:?} that is not in use", frame);
+        self.clear(Self::frame_index(frame));
+    }
+}
@@ -0,0 +1,258 @@
+use x86_64::{
+    structures::paging::{
+        PhysFrame,
+        PageSize,
+        Size4KiB,
+        FrameAllocator,
+        FrameDeallocator,
+    },
+    VirtAddr,
+    PhysAddr,
+};
+
+use bootloader::bootinfo::{
+    MemoryMap,
+    MemoryRegionType,
+};
+
+use core::slice;
+
+/// The largest block order, a block of order n spans 2^n frames.
+/// Order 18 blocks are 1GiB large, which covers all page sizes.
+pub const MAX_ORDER: usize = 18;
+const ORDERS: usize = MAX_ORDER + 1;
+
+// marks a frame that is not the first frame of a free block
+const NOT_FREE: u8 = 0xff;
+// marks the end of a free list
+const NONE: usize = usize::MAX;
+
+// stored in the first frame of every free block
+struct FreeBlock {
+    prev: usize,
+    next: usize,
+}
+
+/// A buddy allocator over the usable frames of the bootloader's memory map.
+///
+/// Memory is managed in blocks of 2^order contiguous frames that are aligned to
+/// their own size. Allocating splits larger blocks in halves ("buddies") until a
+/// block of the requested order is left, freeing merges a block with its buddy
+/// as long as the buddy is free too.
+/// Free blocks are kept in one doubly linked list per order, the list nodes live
+/// in the free frames themselves. One byte per frame records the order of every
+/// free block, so a buddy can be looked up without walking the lists.
+pub struct BuddyFrameAllocator {
+    physical_mem_offset: VirtAddr,
+    // order of the free block starting at a frame, NOT_FREE otherwise
+    orders: &'static mut [u8],
+    free_lists: [usize; ORDERS],
+    free_blocks: [usize; ORDERS],
+    free_frames: usize,
+}
+
+/// A snapshot of how the free memory of a BuddyFrameAllocator is split up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuddyStats {
+    /// number of free frames
+    pub free_frames: usize,
+    /// number of free blocks of each order
+    pub free_blocks: [usize; ORDERS],
+}
+
+impl BuddyStats {
+    /// Returns the order of the largest free block, if any.
+    pub fn largest_free_order(&self) -> Option<usize> {
+        self.free_blocks.iter().rposition(|&count| count > 0)
+    }
+
+    /// Returns how fragmented free memory is, in percent.
+    ///
+    /// 0 means all free frames could be handed out as one block, values close
+    /// to 100 mean free memory is scattered over many small blocks.
+    pub fn fragmentation(&self) -> usize {
+        match self.largest_free_order() {
+            Some(order) => 100 - (100 << order) / self.free_frames,
+            None => 0,
+        }
+    }
+}
+
+impl BuddyFrameAllocator {
+    /// Creates a BuddyFrameAllocator from the passed memory map.
+    ///
+    /// This function is unsafe because the caller must guarantee that the memory map
+    /// passed is valid, that all frames marked as 'USABLE' in it are really unused and
+    /// that the complete physical memory is mapped at 'physical_mem_offset'.
+    /// It must not be used together with another allocator over the same memory map.
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_mem_offset: VirtAddr) -> Self {
+        let usable_regions = || memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable);
+
+        // one byte of metadata for each frame up to the highest usable one
+        let frame_count = usable_regions()
+            .map(|r| r.range.end_frame_number)
+            .max()
+            .unwrap_or(0) as usize;
+        let orders_frames = (frame_count as u64).div_ceil(Size4KiB::SIZE);
+        let orders_region = usable_regions()
+            .find(|r| r.range.end_frame_number - r.range.start_frame_number >= orders_frames)
+            .expect("no usable region large enough for the buddy metadata");
+        let orders_start = orders_region.range.start_frame_number;
+        let orders_ptr: *mut u8 = (physical_mem_offset + orders_region.range.start_addr()).as_mut_ptr();
+        let orders = slice::from_raw_parts_mut(orders_ptr, frame_count);
+        for order in orders.iter_mut() {
+            *order = NOT_FREE;
+        }
+
+        let mut allocator = BuddyFrameAllocator {
+            physical_mem_offset,
+            orders,
+            free_lists: [NONE; ORDERS],
+            free_blocks: [0; ORDERS],
+            free_frames: 0,
+        };
+        for region in usable_regions() {
+            let mut start = region.range.start_frame_number;
+            if start == orders_start {
+                // skip the frames holding the metadata
+                start += orders_frames;
+            }
+            // hand the region out in the largest aligned blocks that fit
+            while start < region.range.end_frame_number {
+                let remaining = region.range.end_frame_number - start;
+                let order = (start.trailing_zeros() as usize)
+                    .min(63 - remaining.leading_zeros() as usize)
+                    .min(MAX_ORDER);
+                allocator.free_block(start as usize, order);
+                start += 1 << order;
+            }
+        }
+        allocator
+    }
+
+    /// Allocates a block of 2^order contiguous frames, aligned to its size.
+    /// Returns the first frame of the block.
+    pub fn allocate(&mut self, order: usize) -> Option<PhysFrame> {
+        // the smallest non-empty list that can satisfy the request
+        let mut block_order = (order..ORDERS).find(|&o| self.free_lists[o] != NONE)?;
+        let index = self.free_lists[block_order];
+        self.remove(index, block_order);
+        // split off the upper halves until the block has the requested size
+        while block_order > order {
+            block_order -= 1;
+            self.push(index + (1 << block_order), block_order);
+        }
+        self.free_frames -= 1 << order;
+        Some(Self::frame(index))
+    }
+
+    /// Frees a block of 2^order frames previously returned by `allocate`.
+    ///
+    /// This function is unsafe because the caller must guarantee that the block
+    /// was allocated with the same order and is no longer in use.
+    pub unsafe fn deallocate(&mut self, frame: PhysFrame, order: usize) {
+        let index = (frame.start_address().as_u64() / Size4KiB::SIZE) as usize;
+        assert!(order <= MAX_ORDER, "invalid block order {}", order);
+        assert_eq!(index % (1 << order), 0, "block {:?} is not aligned to order {}", frame, order);
+        // after merging with its buddy a freed block is part of a larger free block
+        if let Some(start) = self.free_block_covering(index, order) {
+            panic!("deallocating {:?}, which lies in the free block at {:?}", frame, Self::frame(start));
+        }
+        self.free_block(index, order);
+    }
+
+    // returns the start of the free block of 'order' or higher that 'index' lies in
+    fn free_block_covering(&self, index: usize, order: usize) -> Option<usize> {
+        (order..=MAX_ORDER)
+            .map(|order| (order, index & !((1 << order) - 1)))
+            .find(|&(order, start)| self.orders[start] == order as u8)
+            .map(|(_, start)| start)
+    }
+
+    /// Returns the number of frames that are currently free.
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    /// Reports how the free memory is split into blocks.
+    pub fn stats(&self) -> BuddyStats {
+        BuddyStats {
+            free_frames: self.free_frames,
+            free_blocks: self.free_blocks,
+        }
+    }
+
+    // returns the block to the free lists, merging it with its buddies
+    fn free_block(&mut self, mut index: usize, mut order: usize) {
+        self.free_frames += 1 << order;
+        while order < MAX_ORDER {
+            let buddy = index ^ (1 << order);
+            if self.orders.get(buddy) != Some(&(order as u8)) {
+                break;
+            }
+            self.remove(buddy, order);
+            index = index.min(buddy);
+            order += 1;
+        }
+        self.push(index, order);
+    }
+
+    fn push(&mut self, index: usize, order: usize) {
+        let head = self.free_lists[order];
+        *self.node(index) = FreeBlock { prev: NONE, next: head };
+        if head != NONE {
+            self.node(head).prev = index;
+        }
+        self.free_lists[order] = index;
+        self.free_blocks[order] += 1;
+        self.orders[index] = order as u8;
+    }
+
+    fn remove(&mut self, index: usize, order: usize) {
+        let (prev, next) = {
+            let node = self.node(index);
+            (node.prev, node.next)
+        };
+        if prev == NONE {
+            self.free_lists[order] = next;
+        } else {
+            self.node(prev).next = next;
+        }
+        if next != NONE {
+            self.node(next).prev = prev;
+        }
+        self.free_blocks[order] -= 1;
+        self.orders[index] = NOT_FREE;
+    }
+
+    fn node(&mut self, index: usize) -> &mut FreeBlock {
+        let addr = self.physical_mem_offset + index as u64 * Size4KiB::SIZE;
+        unsafe { &mut *addr.as_mut_ptr() }
+    }
+
+    fn frame(index: usize) -> PhysFrame {
+        PhysFrame::containing_address(PhysAddr::new(index as u64 * Size4KiB::SIZE))
+    }
+
+    // the block order that matches the page size S
+    fn order_of<S: PageSize>() -> usize {
+        (S::SIZE / Size4KiB::SIZE).trailing_zeros() as usize
+    }
+}
+
+unsafe impl<S: PageSize> FrameAllocator<S> for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<S>> {
+        let frame = self.allocate(Self::order_of::<S>())?;
+        // blocks are aligned to their size, so this can't fail
+        PhysFrame::from_start_address(frame.start_address()).ok()
+    }
+}
+
+impl<S: PageSize> FrameDeallocator<S> for BuddyFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<S>) {
+        let frame = PhysFrame::containing_address(frame.start_address());
+        self.deallocate(frame, Self::order_of::<S>());
+    }
+}
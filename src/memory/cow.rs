@@ -0,0 +1,202 @@
+use x86_64::{
+    structures::paging::{
+        mapper::{
+            MappedFrame,
+            MapToError,
+            TranslateResult,
+        },
+        FrameAllocator,
+        FrameDeallocator,
+        Mapper,
+        Page,
+        PageSize,
+        PageTableFlags as Flags,
+        PhysFrame,
+        Size4KiB,
+        Translate,
+    },
+    VirtAddr,
+};
+
+use spin::Mutex;
+
+use super::KernelMemory;
+
+/// Marks a page that shares its frame and gets a copy of it on the first write.
+/// One of the bits of a page table entry the CPU leaves to the OS.
+pub const COPY_ON_WRITE: Flags = Flags::BIT_9;
+
+/// Number of frames that can be shared at the same time. The table has a fixed size
+/// because it is used in the page fault handler, which can't rely on the heap.
+pub const MAX_SHARED_FRAMES: usize = 1024;
+
+/// The reference counts of the frames that are mapped more than once.
+/// Always locked after KERNEL_MEMORY.
+pub static SHARED_FRAMES: Mutex<SharedFrames> = Mutex::new(SharedFrames::new());
+
+#[derive(Debug)]
+pub enum CowError {
+    /// the page to share is not mapped
+    NotMapped(Page),
+    /// the page to share is part of a huge page, only 4KiB pages can be shared
+    HugePage(Page),
+    /// mapping the shared frame at the destination failed
+    Map(Page, MapToError<Size4KiB>),
+    /// MAX_SHARED_FRAMES frames are shared already
+    TooManySharedFrames,
+}
+
+/// Counts how many mappings use each shared frame.
+/// Frames that are not in the table have a single owner.
+pub struct SharedFrames {
+    entries: [Option<(PhysFrame, usize)>; MAX_SHARED_FRAMES],
+}
+
+impl SharedFrames {
+    pub const fn new() -> Self {
+        SharedFrames {
+            entries: [None; MAX_SHARED_FRAMES],
+        }
+    }
+
+    /// Returns the number of mappings of 'frame'.
+    pub fn owners(&self, frame: PhysFrame) -> usize {
+        self.entry(frame).map_or(1, |index| self.entries[index].unwrap().1)
+    }
+
+    /// Adds an owner to 'frame', returns the new number of owners.
+    pub fn share(&mut self, frame: PhysFrame) -> Result<usize, CowError> {
+        let index = match self.entry(frame) {
+            Some(index) => index,
+            None => {
+                let index = self.entries.iter().position(Option::is_none)
+                    .ok_or(CowError::TooManySharedFrames)?;
+                self.entries[index] = Some((frame, 1));
+                index
+            }
+        };
+        let (_, owners) = self.entries[index].as_mut().unwrap();
+        *owners += 1;
+        Ok(*owners)
+    }
+
+    /// Removes an owner from 'frame', returns the number of owners left.
+    /// 0 means the frame is unused now and can be freed.
+    pub fn release(&mut self, frame: PhysFrame) -> usize {
+        let index = match self.entry(frame) {
+            Some(index) => index,
+            None => return 0,
+        };
+        let (_, owners) = self.entries[index].as_mut().unwrap();
+        *owners -= 1;
+        let owners = *owners;
+        if owners == 1 {
+            // a frame with a single owner is not shared anymore
+            self.entries[index] = None;
+        }
+        owners
+    }
+
+    fn entry(&self, frame: PhysFrame) -> Option<usize> {
+        self.entries.iter().position(|entry| matches!(entry, Some((shared, _)) if *shared == frame))
+    }
+}
+
+impl KernelMemory {
+    /// Maps the 4KiB pages of the 'size' bytes at 'src' a second time at 'dst', sharing
+    /// the frames. Writable pages become read only and copy-on-write in both places,
+    /// the first write to one of them gives that mapping its own copy of the frame.
+    ///
+    /// If sharing fails halfway, the pages shared before stay shared.
+    pub fn map_copy_on_write(&mut self, src: VirtAddr, dst: VirtAddr, size: u64) -> Result<(), CowError> {
+        for offset in (0..size).step_by(Size4KiB::SIZE as usize) {
+            let src_page = Page::containing_address(src + offset);
+            let dst_page = Page::containing_address(dst + offset);
+            let (frame, flags) = match self.mapper.translate(src_page.start_address()) {
+                TranslateResult::Mapped { frame: MappedFrame::Size4KiB(frame), flags, .. } => (frame, flags),
+                TranslateResult::Mapped { .. } => return Err(CowError::HugePage(src_page)),
+                _ => return Err(CowError::NotMapped(src_page)),
+            };
+            let shared_flags = if flags.contains(Flags::WRITABLE) {
+                (flags - Flags::WRITABLE) | COPY_ON_WRITE
+            } else {
+                flags
+            };
+
+            SHARED_FRAMES.lock().share(frame)?;
+            let mapped = unsafe { self.map_keeping_tables_writable(dst_page, frame, shared_flags) };
+            if let Err(err) = mapped {
+                SHARED_FRAMES.lock().release(frame);
+                return Err(CowError::Map(dst_page, err));
+            }
+            unsafe {
+                self.mapper.update_flags(src_page, shared_flags)
+                    .expect("the page was just translated")
+                    .flush();
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a write to the copy-on-write page 'addr' lies in. If the frame is still
+    /// shared, the page gets a copy of it, the last owner keeps the original frame.
+    /// Either way the page is writable again afterwards.
+    ///
+    /// Returns false if the page is not a copy-on-write page or no frame is left for the copy.
+    pub fn resolve_copy_on_write(&mut self, addr: VirtAddr) -> bool {
+        let page: Page = Page::containing_address(addr);
+        let (frame, flags) = match self.mapper.translate(addr) {
+            TranslateResult::Mapped { frame: MappedFrame::Size4KiB(frame), flags, .. }
+                if flags.contains(COPY_ON_WRITE) => (frame, flags),
+            _ => return false,
+        };
+        let writable = (flags - COPY_ON_WRITE - Flags::ACCESSED - Flags::DIRTY) | Flags::WRITABLE;
+
+        let mut shared_frames = SHARED_FRAMES.lock();
+        if shared_frames.owners(frame) == 1 {
+            unsafe {
+                self.mapper.update_flags(page, writable)
+                    .expect("the page was just translated")
+                    .flush();
+            }
+            return true;
+        }
+
+        let copy = match self.frame_allocator.allocate_frame() {
+            Some(copy) => copy,
+            None => return false,
+        };
+        let phys_offset = self.mapper.phys_offset();
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                (phys_offset + frame.start_address().as_u64()).as_ptr::<u8>(),
+                (phys_offset + copy.start_address().as_u64()).as_mut_ptr::<u8>(),
+                Size4KiB::SIZE as usize,
+            );
+            self.mapper.unmap(page).expect("the page was just translated").1.flush();
+            self.map_keeping_tables_writable(page, copy, writable)
+                .expect("remapping an unmapped page failed");
+        }
+        shared_frames.release(frame);
+        true
+    }
+
+    // frees 'frame' unless other mappings still share it
+    pub(super) unsafe fn release_frame(&mut self, frame: PhysFrame) {
+        if SHARED_FRAMES.lock().release(frame) == 0 {
+            self.frame_allocator.deallocate_frame(frame);
+        }
+    }
+
+    // the page tables on the way stay writable, so that the page can be made
+    // writable later by only changing its own entry
+    unsafe fn map_keeping_tables_writable(&mut self, page: Page, frame: PhysFrame, flags: Flags)
+    -> Result<(), MapToError<Size4KiB>>
+    {
+        let table_flags = Flags::PRESENT | Flags::WRITABLE | (flags & Flags::USER_ACCESSIBLE);
+        self.mapper
+            .map_to_with_table_flags(page, frame, flags, table_flags, &mut self.frame_allocator)?
+            .flush();
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use core::fmt;
+
+use x86_64::{
+    structures::paging::{
+        PageTable,
+        PageTableFlags as Flags,
+    },
+    PhysAddr,
+    VirtAddr,
+};
+
+use crate::serial_println;
+
+use super::KERNEL_MEMORY;
+
+/// A run of virtual memory that is mapped to contiguous physical memory with the same flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    pub start: VirtAddr,
+    pub phys: PhysAddr,
+    pub size: u64,
+    /// The flags that apply to the run. WRITABLE and USER_ACCESSIBLE are only set if
+    /// every level of the page tables allows it, NO_EXECUTE if any level forbids it.
+    /// HUGE_PAGE marks 2MiB and 1GiB pages.
+    pub flags: Flags,
+}
+
+impl Mapping {
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        addr >= self.start && addr.as_u64() - self.start.as_u64() < self.size
+    }
+}
+
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#018x}..{:#018x} -> {:#014x} {:>8} KiB {:?}",
+            self.start.as_u64(),
+            // the end of the lower half is not a canonical address
+            self.start.as_u64().wrapping_add(self.size),
+            self.phys.as_u64(),
+            self.size / 1024,
+            self.flags
+        )
+    }
+}
+
+/// An entry the translation of an address went through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub level: u8,
+    pub index: usize,
+    /// physical address of the table the entry is in
+    pub table: PhysAddr,
+    pub addr: PhysAddr,
+    pub flags: Flags,
+}
+
+/// The way from a virtual address through the page tables to its physical address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Translation {
+    pub addr: VirtAddr,
+    /// the entries walked from level 4 down, the walk stops at a non present entry or a huge page
+    pub entries: [Option<Entry>; 4],
+    /// the page 'addr' lies in, None if it is not mapped
+    pub page: Option<Mapping>,
+}
+
+impl Translation {
+    /// Returns the physical address 'addr' is mapped to.
+    pub fn phys(&self) -> Option<PhysAddr> {
+        self.page.map(|page| page.phys + (self.addr - page.start))
+    }
+}
+
+impl fmt::Display for Translation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "translation of {:#x}:", self.addr.as_u64())?;
+        for entry in self.entries.iter().flatten() {
+            writeln!(
+                f,
+                "    level {} entry {:>3} in table {:#x}: {:#x} {:?}",
+                entry.level,
+                entry.index,
+                entry.table.as_u64(),
+                entry.addr.as_u64(),
+                entry.flags
+            )?;
+        }
+        match (self.page, self.phys()) {
+            (Some(page), Some(phys)) => write!(
+                f,
+                "    -> {:#x} in a {} KiB page, {:?}",
+                phys.as_u64(),
+                page.size / 1024,
+                page.flags
+            ),
+            _ => write!(f, "    -> not mapped"),
+        }
+    }
+}
+
+// the access rights of a page are the combination of the entries on the way to it,
+// ACCESSED and DIRTY are left out, they change all the time
+fn combine(parent: Flags, entry: Flags) -> Flags {
+    let inherited = Flags::WRITABLE | Flags::USER_ACCESSIBLE;
+    let mut flags = entry & !(inherited | Flags::ACCESSED | Flags::DIRTY);
+    flags |= entry & parent & inherited;
+    flags |= parent & Flags::NO_EXECUTE;
+    flags
+}
+
+fn page_size(level: u8) -> u64 {
+    1 << (12 + 9 * (level as u64 - 1))
+}
+
+// entries of level 1 map a page, those of level 2 and 3 do if they are huge
+fn is_leaf(level: u8, flags: Flags) -> bool {
+    level == 1 || (level < 4 && flags.contains(Flags::HUGE_PAGE))
+}
+
+unsafe fn table_at(phys_offset: VirtAddr, addr: PhysAddr) -> &'static PageTable {
+    &*(phys_offset + addr.as_u64()).as_ptr::<PageTable>()
+}
+
+/// Walks all four levels of the page tables and calls 'f' for each mapped range,
+/// in address order. Contiguous pages with the same flags are reported as one range.
+///
+/// Nothing is allocated, so it also works while the heap is broken.
+///
+/// This function is unsafe because the caller must guarantee that all of physical
+/// memory is mapped at 'phys_offset' and that the tables are not changed meanwhile.
+pub unsafe fn for_each_mapping(level_4_table: &PageTable, phys_offset: VirtAddr, f: &mut dyn FnMut(Mapping)) {
+    let mut run: Option<Mapping> = None;
+    walk(level_4_table, phys_offset, 4, 0, Flags::WRITABLE | Flags::USER_ACCESSIBLE, &mut |page| {
+        match run.as_mut() {
+            Some(run) if run.start.as_u64().wrapping_add(run.size) == page.start.as_u64()
+                && run.phys + run.size == page.phys
+                && run.flags == page.flags => run.size += page.size,
+            _ => {
+                if let Some(run) = run.replace(page) {
+                    f(run);
+                }
+            }
+        }
+    });
+    if let Some(run) = run {
+        f(run);
+    }
+}
+
+unsafe fn walk(table: &PageTable, phys_offset: VirtAddr, level: u8, base: u64, parent: Flags, f: &mut dyn FnMut(Mapping)) {
+    for (index, entry) in table.iter().enumerate() {
+        if !entry.flags().contains(Flags::PRESENT) {
+            continue;
+        }
+        let start = base | (index as u64) << (12 + 9 * (level as u64 - 1));
+        let flags = combine(parent, entry.flags());
+        if is_leaf(level, entry.flags()) {
+            f(Mapping {
+                // sets the upper bits for the higher half
+                start: VirtAddr::new_truncate(start),
+                phys: entry.addr(),
+                size: page_size(level),
+                flags,
+            });
+        } else {
+            walk(table_at(phys_offset, entry.addr()), phys_offset, level - 1, start, flags, f);
+        }
+    }
+}
+
+/// Translates 'addr' and records every entry on the way, so it shows where a
+/// translation ends and which level took away a right.
+///
+/// This function is unsafe for the same reasons as `for_each_mapping`.
+pub unsafe fn translate(level_4_table: &PageTable, phys_offset: VirtAddr, addr: VirtAddr) -> Translation {
+    let mut translation = Translation { addr, entries: [None; 4], page: None };
+    let mut table = level_4_table;
+    let mut flags = Flags::WRITABLE | Flags::USER_ACCESSIBLE;
+    for level in (1..=4u8).rev() {
+        let index = (addr.as_u64() >> (12 + 9 * (level as u64 - 1))) as usize & 0x1ff;
+        let entry = &table[index];
+        let table_addr = VirtAddr::from_ptr(table).as_u64() - phys_offset.as_u64();
+        translation.entries[4 - level as usize] = Some(Entry {
+            level,
+            index,
+            table: PhysAddr::new(table_addr),
+            addr: entry.addr(),
+            flags: entry.flags(),
+        });
+        if !entry.flags().contains(Flags::PRESENT) {
+            break;
+        }
+        flags = combine(flags, entry.flags());
+        if is_leaf(level, entry.flags()) {
+            let size = page_size(level);
+            translation.page = Some(Mapping {
+                start: addr.align_down(size),
+                phys: entry.addr(),
+                size,
+                flags,
+            });
+            break;
+        }
+        table = table_at(phys_offset, entry.addr());
+    }
+    translation
+}
+
+/// Prints the mapped ranges of the kernel's page tables over serial.
+pub fn print_mappings() {
+    let mut kernel_memory = KERNEL_MEMORY.lock();
+    let mapper = match kernel_memory.as_mut() {
+        Some(kernel_memory) => &mut kernel_memory.mapper,
+        None => {
+            serial_println!("page tables: kernel memory not initialized");
+            return;
+        }
+    };
+    let phys_offset = mapper.phys_offset();
+    serial_println!("page tables:");
+    unsafe {
+        for_each_mapping(mapper.level_4_table(), phys_offset, &mut |mapping| {
+            serial_println!("    {}", mapping);
+        });
+    }
+}
+
+/// Prints how 'addr' is translated by the kernel's page tables over serial.
+pub fn print_translation(addr: VirtAddr) {
+    let mut kernel_memory = KERNEL_MEMORY.lock();
+    let mapper = match kernel_memory.as_mut() {
+        Some(kernel_memory) => &mut kernel_memory.mapper,
+        None => {
+            serial_println!("page tables: kernel memory not initialized");
+            return;
+        }
+    };
+    let phys_offset = mapper.phys_offset();
+    serial_println!("{}", unsafe { translate(mapper.level_4_table(), phys_offset, addr) });
+}
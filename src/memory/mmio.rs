@@ -0,0 +1,166 @@
+use core::mem;
+
+use x86_64::{
+    structures::paging::{
+        mapper::MapToError,
+        PageSize,
+        PageTableFlags as Flags,
+        Size4KiB,
+    },
+    PhysAddr,
+    VirtAddr,
+};
+
+use volatile::Volatile;
+
+use super::{
+    vma::{
+        Region,
+        RegionError,
+        RegionKind,
+        VIRTUAL_REGIONS,
+    },
+    KERNEL_MEMORY,
+};
+
+/// How the CPU may cache the memory of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// every access goes to the device, for registers
+    Uncached,
+    /// reads are cached, writes go straight to the device, e.g. for framebuffers
+    WriteThrough,
+}
+
+impl CacheMode {
+    fn flags(self) -> Flags {
+        match self {
+            CacheMode::Uncached => Flags::NO_CACHE | Flags::WRITE_THROUGH,
+            CacheMode::WriteThrough => Flags::WRITE_THROUGH,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MmioError {
+    /// no virtual range is left for the mapping
+    Region(RegionError),
+    /// mapping the pages failed
+    Map(MapToError<Size4KiB>),
+    /// the kernel memory has not been handed over with `init_kernel_memory` yet
+    NoKernelMemory,
+}
+
+/// The registers of a device, mapped into the kernel's address space.
+/// The mapping is removed again when the region is dropped.
+pub struct MmioRegion {
+    region: Region,
+    phys: PhysAddr,
+    len: usize,
+}
+
+/// Maps the 'len' bytes of device memory at 'phys' uncached, see `map_mmio_with`.
+///
+/// This function is unsafe for the same reasons as `map_mmio_with`.
+pub unsafe fn map_mmio(phys: PhysAddr, len: usize) -> Result<MmioRegion, MmioError> {
+    map_mmio_with(phys, len, CacheMode::Uncached)
+}
+
+/// Maps the 'len' bytes of device memory at 'phys' to a free virtual range.
+/// 'phys' doesn't have to be page aligned.
+///
+/// This function is unsafe because the caller must guarantee that the physical range
+/// belongs to a device, mapping memory that is in use elsewhere breaks memory safety.
+pub unsafe fn map_mmio_with(phys: PhysAddr, len: usize, cache_mode: CacheMode) -> Result<MmioRegion, MmioError> {
+    let page_start = phys.align_down(Size4KiB::SIZE);
+    let size = (phys + len as u64).align_up(Size4KiB::SIZE) - page_start;
+
+    let region = VIRTUAL_REGIONS.lock()
+        .allocate(size, RegionKind::Mmio, "mmio")
+        .map_err(MmioError::Region)?;
+    let flags = Flags::PRESENT | Flags::WRITABLE | Flags::NO_EXECUTE | cache_mode.flags();
+    let mapped = match KERNEL_MEMORY.lock().as_mut() {
+        Some(kernel_memory) => kernel_memory
+            .map_physical_range(region.start, page_start, size, flags)
+            .map_err(MmioError::Map),
+        None => Err(MmioError::NoKernelMemory),
+    };
+    if let Err(err) = mapped {
+        VIRTUAL_REGIONS.lock().release(region.start);
+        return Err(err);
+    }
+    Ok(MmioRegion { region, phys, len })
+}
+
+impl MmioRegion {
+    /// Returns the virtual address the physical address of the region is mapped to.
+    pub fn base(&self) -> VirtAddr {
+        self.region.start + (self.phys - self.phys.align_down(Size4KiB::SIZE))
+    }
+
+    pub fn phys(&self) -> PhysAddr {
+        self.phys
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the register of type T at 'offset' bytes into the region.
+    /// Panics if it doesn't lie in the region or is not aligned for T.
+    pub fn register<T: Copy>(&self, offset: usize) -> &Volatile<T> {
+        unsafe { &*self.register_ptr(offset) }
+    }
+
+    /// Like `register`, but the register can be written.
+    pub fn register_mut<T: Copy>(&mut self, offset: usize) -> &mut Volatile<T> {
+        unsafe { &mut *self.register_ptr(offset) }
+    }
+
+    /// Reads the register of type T at 'offset'.
+    pub fn read<T: Copy>(&self, offset: usize) -> T {
+        self.register(offset).read()
+    }
+
+    /// Writes 'value' to the register of type T at 'offset'.
+    pub fn write<T: Copy>(&mut self, offset: usize, value: T) {
+        self.register_mut(offset).write(value)
+    }
+
+    /// Returns the registers as a struct that describes their layout.
+    ///
+    /// This function is unsafe because the caller must guarantee that R is a
+    /// `#[repr(C)]` struct of `Volatile` fields that matches the device and fits
+    /// into the region.
+    pub unsafe fn registers<R>(&mut self) -> &mut R {
+        assert!(mem::size_of::<R>() <= self.len, "register block larger than the region");
+        &mut *self.base().as_mut_ptr::<R>()
+    }
+
+    fn register_ptr<T: Copy>(&self, offset: usize) -> *mut Volatile<T> {
+        assert!(
+            offset + mem::size_of::<T>() <= self.len,
+            "register at {:#x} out of the MMIO region of {:#x} bytes",
+            offset,
+            self.len
+        );
+        let addr = self.base() + offset;
+        assert!(addr.is_aligned(mem::align_of::<T>() as u64), "unaligned register at {:#x}", offset);
+        addr.as_mut_ptr()
+    }
+}
+
+impl Drop for MmioRegion {
+    fn drop(&mut self) {
+        if let Some(kernel_memory) = KERNEL_MEMORY.lock().as_mut() {
+            // the frames belong to the device, so they are not freed
+            kernel_memory.unmap_physical_range(self.region.start, self.region.size)
+                .expect("unmapping an MMIO region failed");
+        }
+        VIRTUAL_REGIONS.lock().release(self.region.start);
+    }
+}
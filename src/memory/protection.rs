@@ -0,0 +1,182 @@
+use bootloader::bootinfo::{
+    MemoryMap,
+    MemoryRegionType,
+};
+
+use x86_64::{
+    registers::{
+        control::{
+            Cr0,
+            Cr0Flags,
+        },
+        model_specific::{
+            Efer,
+            EferFlags,
+        },
+    },
+    structures::paging::{
+        mapper::{
+            FlagUpdateError,
+            MappedFrame,
+            TranslateResult,
+        },
+        Mapper,
+        Page,
+        PageSize,
+        PageTableFlags as Flags,
+        Size1GiB,
+        Size2MiB,
+        Size4KiB,
+        Translate,
+    },
+    PhysAddr,
+    VirtAddr,
+};
+
+use super::{
+    inspect::{
+        self,
+        Mapping,
+    },
+    report,
+    KernelMemory,
+    KERNEL_MEMORY,
+};
+
+/// Turns on EFER.NXE, without it NO_EXECUTE is a reserved bit and every page is
+/// executable, and CR0.WP, without it the kernel can write to read-only pages.
+///
+/// Must run before anything is mapped NO_EXECUTE, `memory::init` calls it.
+pub fn enable() {
+    unsafe {
+        Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE));
+        Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT));
+    }
+}
+
+/// Returns true if both no-execute and write protection are turned on.
+pub fn is_enabled() -> bool {
+    Efer::read().contains(EferFlags::NO_EXECUTE_ENABLE) && Cr0::read().contains(Cr0Flags::WRITE_PROTECT)
+}
+
+/// Remaps the code of the kernel image read-only and its stack no-execute, so that no
+/// page of the kernel is both writable and executable.
+///
+/// The frames of the image and the stack are found through the memory map. The alias
+/// of every frame in the mapping of all physical memory stays writable, the page
+/// tables are changed through it, so the whole mapping becomes no-execute instead.
+pub fn protect_kernel(memory_map: &MemoryMap) -> Result<(), FlagUpdateError> {
+    let mut kernel_memory = KERNEL_MEMORY.lock();
+    let kernel_memory = kernel_memory.as_mut().expect("kernel memory not initialized");
+    let phys_offset = kernel_memory.mapper.phys_offset();
+
+    // the tables must not change during a walk, so each walk only looks for the next
+    // range to change, nothing is allocated on the way
+    let mut next = VirtAddr::zero();
+    loop {
+        let mut found: Option<Mapping> = None;
+        unsafe {
+            inspect::for_each_mapping(kernel_memory.mapper.level_4_table(), phys_offset, &mut |mapping| {
+                if found.is_none()
+                    && mapping.start >= next
+                    && mapping.start != phys_offset + mapping.phys.as_u64()
+                    && protected_flags(memory_map, mapping.phys, mapping.flags) != mapping.flags
+                {
+                    found = Some(mapping);
+                }
+            });
+        }
+        let mapping = match found {
+            Some(mapping) => mapping,
+            None => break,
+        };
+        for offset in (0..mapping.size).step_by(Size4KiB::SIZE as usize) {
+            protect_page(kernel_memory, memory_map, mapping.start + offset)?;
+        }
+        next = mapping.start + mapping.size;
+    }
+    protect_physical_memory(kernel_memory)
+}
+
+// makes the mapping of all physical memory no-execute, the bootloader maps it with
+// huge pages, which are updated as a whole
+fn protect_physical_memory(kernel_memory: &mut KernelMemory) -> Result<(), FlagUpdateError> {
+    let phys_offset = kernel_memory.mapper.phys_offset();
+    let mut next = VirtAddr::zero();
+    loop {
+        let mut found: Option<Mapping> = None;
+        unsafe {
+            inspect::for_each_mapping(kernel_memory.mapper.level_4_table(), phys_offset, &mut |mapping| {
+                if found.is_none()
+                    && mapping.start >= next
+                    && mapping.start == phys_offset + mapping.phys.as_u64()
+                    && !mapping.flags.contains(Flags::NO_EXECUTE)
+                {
+                    found = Some(mapping);
+                }
+            });
+        }
+        let mapping = match found {
+            Some(mapping) => mapping,
+            None => return Ok(()),
+        };
+        let mut addr = mapping.start;
+        while addr < mapping.start + mapping.size {
+            addr += no_execute_page(kernel_memory, addr)?;
+        }
+        next = mapping.start + mapping.size;
+    }
+}
+
+// sets NO_EXECUTE on the page 'addr' lies in, whatever its size, returns the size
+fn no_execute_page(kernel_memory: &mut KernelMemory, addr: VirtAddr) -> Result<u64, FlagUpdateError> {
+    let mapper = &mut kernel_memory.mapper;
+    let (frame, flags) = match mapper.translate(addr) {
+        TranslateResult::Mapped { frame, flags, .. } => (frame, flags | Flags::NO_EXECUTE),
+        _ => return Err(FlagUpdateError::PageNotMapped),
+    };
+    unsafe {
+        match frame {
+            MappedFrame::Size4KiB(_) => {
+                let page: Page<Size4KiB> = Page::containing_address(addr);
+                mapper.update_flags(page, flags)?.flush();
+            }
+            MappedFrame::Size2MiB(_) => {
+                let page: Page<Size2MiB> = Page::containing_address(addr);
+                mapper.update_flags(page, flags)?.flush();
+            }
+            MappedFrame::Size1GiB(_) => {
+                let page: Page<Size1GiB> = Page::containing_address(addr);
+                mapper.update_flags(page, flags)?.flush();
+            }
+        }
+    }
+    // the next page starts at the end of this one, 'addr' need not be its start
+    Ok(frame.size() - (addr.as_u64() & (frame.size() - 1)))
+}
+
+// the flags of the kernel page at 'phys' has to have, code loses WRITABLE and the
+// stack gains NO_EXECUTE
+fn protected_flags(memory_map: &MemoryMap, phys: PhysAddr, flags: Flags) -> Flags {
+    let region_type = report::region_of(memory_map, phys).map(|region| region.region_type);
+    match region_type {
+        Some(MemoryRegionType::Kernel) if !flags.contains(Flags::NO_EXECUTE) => flags - Flags::WRITABLE,
+        Some(MemoryRegionType::KernelStack) => flags | Flags::NO_EXECUTE,
+        _ => flags,
+    }
+}
+
+fn protect_page(kernel_memory: &mut KernelMemory, memory_map: &MemoryMap, addr: VirtAddr) -> Result<(), FlagUpdateError> {
+    let (phys, flags) = match kernel_memory.mapper.translate(addr) {
+        TranslateResult::Mapped { frame: MappedFrame::Size4KiB(frame), flags, .. } => (frame.start_address(), flags),
+        // the bootloader maps the kernel with 4KiB pages
+        TranslateResult::Mapped { .. } => return Err(FlagUpdateError::ParentEntryHugePage),
+        _ => return Err(FlagUpdateError::PageNotMapped),
+    };
+    let protected = protected_flags(memory_map, phys, flags);
+    if protected != flags {
+        let page: Page<Size4KiB> = Page::containing_address(addr);
+        unsafe { kernel_memory.mapper.update_flags(page, protected)?.flush() };
+    }
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use core::fmt;
+
+use bootloader::bootinfo::{
+    MemoryMap,
+    MemoryRegion,
+    MemoryRegionType,
+};
+
+use x86_64::{
+    structures::paging::PhysFrame,
+    PhysAddr,
+};
+
+use crate::serial_println;
+
+/// What the physical memory of the machine is used for, summed up from the
+/// bootloader's memory map. All sizes are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemorySummary {
+    /// memory the frame allocators may hand out
+    pub usable: u64,
+    /// the kernel image and its stack
+    pub kernel: u64,
+    /// the page tables the bootloader set up
+    pub page_tables: u64,
+    /// the bootloader itself, the boot info and frame zero
+    pub bootloader: u64,
+    /// memory reserved by the firmware, ACPI tables and bad memory
+    pub reserved: u64,
+    /// end of the highest region of the memory map
+    pub highest_address: u64,
+    /// number of regions in the memory map
+    pub regions: usize,
+}
+
+impl MemorySummary {
+    pub fn new(memory_map: &MemoryMap) -> Self {
+        let mut summary = MemorySummary::default();
+        for region in memory_map.iter() {
+            let size = region.range.end_addr() - region.range.start_addr();
+            match region.region_type {
+                MemoryRegionType::Usable => summary.usable += size,
+                MemoryRegionType::Kernel | MemoryRegionType::KernelStack => summary.kernel += size,
+                MemoryRegionType::PageTable => summary.page_tables += size,
+                MemoryRegionType::Bootloader
+                | MemoryRegionType::BootInfo
+                | MemoryRegionType::Package
+                | MemoryRegionType::FrameZero
+                | MemoryRegionType::InUse => summary.bootloader += size,
+                MemoryRegionType::Empty => {}
+                _ => summary.reserved += size,
+            }
+            summary.highest_address = summary.highest_address.max(region.range.end_addr());
+            summary.regions += 1;
+        }
+        summary
+    }
+}
+
+impl fmt::Display for MemorySummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "usable RAM:  {:>8} KiB", self.usable / 1024)?;
+        writeln!(f, "kernel:      {:>8} KiB", self.kernel / 1024)?;
+        writeln!(f, "page tables: {:>8} KiB", self.page_tables / 1024)?;
+        writeln!(f, "bootloader:  {:>8} KiB", self.bootloader / 1024)?;
+        writeln!(f, "reserved:    {:>8} KiB", self.reserved / 1024)?;
+        write!(f, "{} regions up to {:#x}", self.regions, self.highest_address)
+    }
+}
+
+/// Returns the region of the memory map 'addr' lies in.
+pub fn region_of(memory_map: &MemoryMap, addr: PhysAddr) -> Option<&MemoryRegion> {
+    let addr = addr.as_u64();
+    memory_map
+        .iter()
+        .find(|region| region.range.start_addr() <= addr && addr < region.range.end_addr())
+}
+
+/// Returns true if 'frame' lies in a usable region, false for every frame the
+/// firmware, the bootloader or the kernel image occupy.
+pub fn is_usable(memory_map: &MemoryMap, frame: PhysFrame) -> bool {
+    region_of(memory_map, frame.start_address())
+        .map_or(false, |region| region.region_type == MemoryRegionType::Usable)
+}
+
+/// Prints every region of the memory map and a summary over serial.
+pub fn print_memory_map(memory_map: &MemoryMap) {
+    serial_println!("physical memory map:");
+    for region in memory_map.iter() {
+        let (start, end) = (region.range.start_addr(), region.range.end_addr());
+        serial_println!(
+            "    {:#012x}..{:#012x} {:>8} KiB {:?}",
+            start,
+            end,
+            (end - start) / 1024,
+            region.region_type
+        );
+    }
+    serial_println!("{}", MemorySummary::new(memory_map));
+}
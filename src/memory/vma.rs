@@ -0,0 +1,248 @@
+use core::fmt;
+
+use x86_64::{
+    structures::paging::{
+        PageSize,
+        Size4KiB,
+    },
+    VirtAddr,
+};
+
+use spin::Mutex;
+
+use crate::println;
+
+use super::address_space::{
+    USER_END,
+    USER_START,
+};
+
+/// Number of regions that can be tracked. The table has a fixed size because
+/// the heap is one of the regions, so it has to exist before the heap does.
+pub const MAX_REGIONS: usize = 64;
+
+/// Range of the kernel's address space that `allocate` hands out regions from.
+/// Fixed regions like the heap live outside of it.
+pub const DYNAMIC_START: u64 = 0x_6000_0000_0000;
+pub const DYNAMIC_END: u64 = 0x_7000_0000_0000;
+
+const PAGE_SIZE: u64 = Size4KiB::SIZE;
+
+/// The regions of the kernel's virtual address space.
+pub static VIRTUAL_REGIONS: Mutex<VirtualRegions> =
+    Mutex::new(VirtualRegions::new(DYNAMIC_START, DYNAMIC_END));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Heap,
+    Stack,
+    Mmio,
+    User,
+    Other,
+}
+
+impl RegionKind {
+    /// Heaps and stacks can run over their ends, so the page right in front of and
+    /// behind them is kept free of other regions and left unmapped as a guard.
+    pub fn is_guarded(self) -> bool {
+        matches!(self, RegionKind::Heap | RegionKind::Stack)
+    }
+}
+
+/// A reserved range of virtual addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: VirtAddr,
+    pub size: u64,
+    pub kind: RegionKind,
+    pub name: &'static str,
+    /// The pages are not mapped up front, the page fault handler backs each one
+    /// with a frame when it is first touched, see `memory::map_on_demand`.
+    pub demand_paged: bool,
+}
+
+impl Region {
+    pub fn end(&self) -> VirtAddr {
+        self.start + self.size
+    }
+
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        addr >= self.start && addr < self.end()
+    }
+
+    // the range including the guard pages
+    fn footprint(&self) -> (u64, u64) {
+        let guard = if self.kind.is_guarded() { PAGE_SIZE } else { 0 };
+        (self.start.as_u64() - guard, self.end().as_u64() + guard)
+    }
+
+    // true if a part of one region lies in the other one or its guard pages
+    fn collides_with(&self, other: &Region) -> bool {
+        let overlaps = |(start, end): (u64, u64), region: &Region| {
+            start < region.end().as_u64() && region.start.as_u64() < end
+        };
+        overlaps(self.footprint(), other) || overlaps(other.footprint(), self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionError {
+    /// start or size are not page aligned, or the size is 0
+    Unaligned,
+    /// the range overlaps the given region or its guard pages
+    Overlap(Region),
+    /// no free range of the requested size is left
+    NoSpace,
+    /// MAX_REGIONS regions are tracked already
+    TableFull,
+    /// a user region outside of USER_START..USER_END, the kernel's part of the
+    /// address space is shared by all address spaces
+    NotUserSpace,
+}
+
+/// Keeps track of which parts of a virtual address space are in use,
+/// so that mappings don't end up on top of each other.
+pub struct VirtualRegions {
+    // sorted by start address, the first 'count' entries are used
+    regions: [Option<Region>; MAX_REGIONS],
+    count: usize,
+    dynamic_start: u64,
+    dynamic_end: u64,
+}
+
+impl VirtualRegions {
+    /// Creates an empty table that hands out regions from 'dynamic_start' to 'dynamic_end'.
+    pub const fn new(dynamic_start: u64, dynamic_end: u64) -> Self {
+        VirtualRegions {
+            regions: [None; MAX_REGIONS],
+            count: 0,
+            dynamic_start,
+            dynamic_end,
+        }
+    }
+
+    /// Reserves the range of 'size' bytes at 'start'.
+    /// Fails if it overlaps another region, or the guard pages of either one.
+    pub fn reserve(&mut self, start: VirtAddr, size: u64, kind: RegionKind, name: &'static str)
+    -> Result<Region, RegionError>
+    {
+        self.insert(Region { start, size, kind, name, demand_paged: false })
+    }
+
+    /// Like `reserve`, but the region is backed page by page as it is touched,
+    /// so large and sparsely used ranges cost no memory up front.
+    pub fn reserve_demand_paged(&mut self, start: VirtAddr, size: u64, kind: RegionKind, name: &'static str)
+    -> Result<Region, RegionError>
+    {
+        self.insert(Region { start, size, kind, name, demand_paged: true })
+    }
+
+    fn insert(&mut self, region: Region) -> Result<Region, RegionError> {
+        let start = region.start;
+        if region.size == 0 || !start.is_aligned(PAGE_SIZE) || region.size % PAGE_SIZE != 0 {
+            return Err(RegionError::Unaligned);
+        }
+        if region.kind == RegionKind::User
+            && (start.as_u64() < USER_START || region.end().as_u64() > USER_END)
+        {
+            return Err(RegionError::NotUserSpace);
+        }
+        if let Some(other) = self.iter().find(|other| region.collides_with(other)) {
+            return Err(RegionError::Overlap(*other));
+        }
+        if self.count == MAX_REGIONS {
+            return Err(RegionError::TableFull);
+        }
+        // insert it at its place in the sorted list
+        let index = self.iter().take_while(|other| other.start < start).count();
+        self.regions[index..=self.count].rotate_right(1);
+        self.regions[index] = Some(region);
+        self.count += 1;
+        Ok(region)
+    }
+
+    /// Finds a free range of 'size' bytes in the dynamic part of the address space
+    /// and reserves it, returns the lowest one that fits.
+    pub fn allocate(&mut self, size: u64, kind: RegionKind, name: &'static str)
+    -> Result<Region, RegionError>
+    {
+        self.allocate_region(size, kind, name, false)
+    }
+
+    /// Like `allocate`, but the region is backed page by page as it is touched.
+    pub fn allocate_demand_paged(&mut self, size: u64, kind: RegionKind, name: &'static str)
+    -> Result<Region, RegionError>
+    {
+        self.allocate_region(size, kind, name, true)
+    }
+
+    fn allocate_region(&mut self, size: u64, kind: RegionKind, name: &'static str, demand_paged: bool)
+    -> Result<Region, RegionError>
+    {
+        if size == 0 || size % PAGE_SIZE != 0 {
+            return Err(RegionError::Unaligned);
+        }
+        if kind == RegionKind::User
+            && (self.dynamic_start < USER_START || self.dynamic_end > USER_END)
+        {
+            return Err(RegionError::NotUserSpace);
+        }
+        let guard = if kind.is_guarded() { PAGE_SIZE } else { 0 };
+        let mut start = self.dynamic_start + guard;
+        loop {
+            if start + size + guard > self.dynamic_end {
+                return Err(RegionError::NoSpace);
+            }
+            let candidate = Region { start: VirtAddr::new(start), size, kind, name, demand_paged };
+            let collision = self.iter().find(|other| candidate.collides_with(other)).copied();
+            match collision {
+                // try again right behind the region in the way
+                Some(other) => start = other.footprint().1.max(other.end().as_u64() + guard),
+                None => return self.insert(candidate),
+            }
+        }
+    }
+
+    /// Gives up the region starting at 'start', returns it if there was one.
+    pub fn release(&mut self, start: VirtAddr) -> Option<Region> {
+        let index = self.iter().position(|region| region.start == start)?;
+        let region = self.regions[index].take();
+        self.regions[index..self.count].rotate_left(1);
+        self.count -= 1;
+        region
+    }
+
+    /// Returns the region 'addr' lies in.
+    pub fn find(&self, addr: VirtAddr) -> Option<Region> {
+        self.iter().find(|region| region.contains(addr)).copied()
+    }
+
+    /// Iterates over the regions, ordered by address.
+    pub fn iter(&self) -> impl Iterator<Item = &Region> {
+        self.regions[..self.count].iter().map(|region| region.as_ref().unwrap())
+    }
+}
+
+impl fmt::Display for VirtualRegions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for region in self.iter() {
+            writeln!(
+                f,
+                "{:#014x}..{:#014x} {:>10} KiB {:?} {}{}",
+                region.start.as_u64(),
+                region.end().as_u64(),
+                region.size / 1024,
+                region.kind,
+                region.name,
+                if region.demand_paged { " (on demand)" } else { "" }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the regions of the kernel's address space.
+pub fn print_layout() {
+    println!("virtual memory layout:");
+    println!("{}", *VIRTUAL_REGIONS.lock());
+}
@@ -8,12 +8,17 @@ use super::{
     TaskId,
 };
 
-use crate::hlt_loop;
+use crate::{
+    allocator::AllocError,
+    hlt_loop,
+    println,
+};
 
 use alloc::{
+    alloc::Layout,
     task::Wake,
-    collections::BTreeMap,
     sync::Arc,
+    vec::Vec,
 };
 use core::task::{
     Context, 
@@ -22,27 +27,70 @@ use core::task::{
 };
 use crossbeam_queue::ArrayQueue;
 
+// the most tasks that can be ready at the same time
+const QUEUE_SIZE: usize = 100;
+
+/// Why a task could not be spawned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// no memory for the task's bookkeeping or its waker
+    Alloc(AllocError),
+    /// the queue of ready tasks is full
+    QueueFull,
+    /// a task with the same id was spawned before
+    DuplicateId,
+}
+
+impl From<AllocError> for SpawnError {
+    fn from(err: AllocError) -> Self {
+        SpawnError::Alloc(err)
+    }
+}
+
+// a task and the waker that puts it back into the queue
+struct Spawned {
+    task: Task,
+    waker: Waker,
+}
+
 pub struct Executor {
-    tasks: BTreeMap<TaskId, Task>,
+    // sorted by task id
+    tasks: Vec<Spawned>,
     task_queue: Arc<ArrayQueue<TaskId>>,
-    waker_cache: BTreeMap<TaskId, Waker>,
 }
 
 impl Executor {
     pub fn new() -> Self {
         Executor { 
-            tasks: BTreeMap::new(), 
+            tasks: Vec::new(), 
             // task IDs
-            task_queue: Arc::new(ArrayQueue::new(100)), 
-            waker_cache: BTreeMap::new(),
+            task_queue: Arc::new(ArrayQueue::new(QUEUE_SIZE)), 
         }
     }
+
+    /// Like `try_spawn`, but panics if the task can't be spawned.
     pub fn spawn(&mut self, task: Task) {
+        self.try_spawn(task).expect("spawning a task failed");
+    }
+
+    /// Adds the task and queues it to be polled.
+    ///
+    /// The waker is created here, so running the tasks never allocates. On an error
+    /// the task is dropped and the executor is left as it was.
+    pub fn try_spawn(&mut self, task: Task) -> Result<(), SpawnError> {
         let task_id = task.id;
-        if self.tasks.insert(task.id, task).is_some() {
-            panic!("task with same Id already exist in tasks");
-        }
-        self.task_queue.push(task_id).expect("queue");
+        let index = match self.tasks.binary_search_by_key(&task_id, |spawned| spawned.task.id) {
+            Ok(_) => return Err(SpawnError::DuplicateId),
+            Err(index) => index,
+        };
+        self.tasks.try_reserve(1).map_err(|_| AllocError {
+            layout: Layout::array::<Spawned>(self.tasks.len() + 1).ok(),
+        })?;
+        let waker = TaskWaker::try_new(task_id, self.task_queue.clone())?;
+        self.task_queue.push(task_id).map_err(|_| SpawnError::QueueFull)?;
+        // there is room reserved, so inserting doesn't allocate
+        self.tasks.insert(index, Spawned { task, waker });
+        Ok(())
     }
 
     fn run_ready_tasks(&mut self) {
@@ -50,28 +98,23 @@ impl Executor {
         let Self {
             tasks,
             task_queue,
-            waker_cache,
         } = self;
 
         // loop over all tasks, remove the last, capturing the task_id
         while let Ok(task_id) = task_queue.pop() {
             // returns a any task(mut) that matches with the task_id
-            let task = match tasks.get_mut(&task_id) {
-                Some(task) => task,
-                None => continue, // task no longer exists
+            let index = match tasks.binary_search_by_key(&task_id, |spawned| spawned.task.id) {
+                Ok(index) => index,
+                Err(_) => continue, // task no longer exists
             };
-            // create a waker for task, using the task_id
-            let waker = waker_cache
-                .entry(task_id)
-                // Create waker, if it doesn't already exist in the waker_cache
-                .or_insert_with(|| TaskWaker::new(task_id, task_queue.clone()));
+            // the waker was created when the task was spawned
+            let Spawned { task, waker } = &mut tasks[index];
             // retrieve the task context and poll task
             let mut context = Context::from_waker(waker);
             match task.poll(&mut context) {
                 Poll::Ready(()) => {
-                    // task done -> remove it and its cached waker
-                    tasks.remove(&task_id);
-                    waker_cache.remove(&task_id);
+                    // task done -> remove it together with its waker
+                    tasks.remove(index);
                 }
                 Poll::Pending => {}
             }
@@ -105,15 +148,19 @@ struct TaskWaker {
 }
 
 impl TaskWaker {
-    fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        Waker::from(Arc::new(TaskWaker {
+    fn try_new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Result<Waker, AllocError> {
+        let waker = Arc::try_new(TaskWaker {
             task_id,
             task_queue,
-        }))
+        }).map_err(|_| AllocError { layout: Some(Layout::new::<TaskWaker>()) })?;
+        Ok(Waker::from(waker))
     }
 
+    // called from interrupt handlers, so it must not panic
     fn wake_task(&self) {
-        self.task_queue.push(self.task_id).expect("task_queue is full");
+        if self.task_queue.push(self.task_id).is_err() {
+            println!("WARNING: task_queue full; dropping wake up");
+        }
     }
 
 }
@@ -36,7 +36,8 @@ static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
 static WAKER: AtomicWaker = AtomicWaker::new();
 
 // called by the interrupt handler
-// must not block or allocate
+// must not block or allocate: the ArrayQueue is allocated once in
+// ScancodeStream::new and pushing to it never allocates
 pub(crate) fn add_scancode(scancode: u8) {
     if let Ok(queue) = SCANCODE_QUEUE.try_get() {
         if let Err(_) = queue.push(scancode) {
@@ -12,6 +12,11 @@ use core::{
 };
 use alloc::boxed::Box;
 
+use crate::allocator::{
+    self,
+    AllocError,
+};
+
 pub mod simple_executor;
 pub mod keyboard;
 pub mod executor;
@@ -34,6 +39,15 @@ impl Task {
         }
     }
 
+    /// Like `Task::new`, but returns an error if there is no memory for the future.
+    pub fn try_new(future: impl Future<Output = ()> + 'static) -> Result<Task, AllocError> {
+        let future: Box<dyn Future<Output = ()>> = allocator::try_box(future)?;
+        Ok(Task {
+            id: TaskId::new(),
+            future: Box::into_pin(future),
+        })
+    }
+
     // should only be called by the executor
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
         // convert self.future from Pin<Box<T>> to Pin<Box<&mut T>>
@@ -0,0 +1,164 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oubre_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+
+use oubre_os::{
+    allocator,
+    interrupts,
+    memory::{
+        self,
+        address_space::{self, AddressSpace, USER_START},
+        bitmap::BitmapFrameAllocator,
+        KERNEL_MEMORY,
+    },
+};
+use x86_64::{
+    structures::paging::{
+        page::PageRange,
+        Page,
+        PageSize,
+        PageTableFlags as Flags,
+        Size4KiB,
+        Translate,
+    },
+    VirtAddr,
+};
+
+const PAGE: u64 = 4096;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+    // kernel level 4 entries missing in an active address space are synced on page faults
+    interrupts::init_idt();
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oubre_os::test_panic_handler(info);
+}
+
+fn free_frames() -> usize {
+    KERNEL_MEMORY.lock().as_ref().unwrap().frame_allocator.free_frames()
+}
+
+fn user_pages(count: u64) -> PageRange {
+    let start = Page::containing_address(VirtAddr::new(USER_START));
+    Page::range(start, start + count)
+}
+
+#[test_case]
+fn kernel_mappings_are_shared() {
+    let heap_value = Box::new(7u64);
+    let addr = VirtAddr::from_ptr(&*heap_value);
+    let kernel_phys = KERNEL_MEMORY.lock().as_ref().unwrap().mapper.translate_addr(addr);
+
+    let mut space = AddressSpace::new().unwrap();
+    assert!(kernel_phys.is_some());
+    assert_eq!(space.mapper().translate_addr(addr), kernel_phys);
+    assert_eq!(space.mapper().translate_addr(VirtAddr::new(USER_START)), None);
+}
+
+#[test_case]
+fn user_pages_are_only_visible_in_their_space() {
+    let mut space = AddressSpace::new().unwrap();
+    space.map_user_pages(user_pages(2), Flags::WRITABLE).unwrap();
+    assert!(!space.is_active());
+
+    unsafe { space.activate() };
+    assert!(space.is_active());
+    let ptr = VirtAddr::new(USER_START + PAGE).as_mut_ptr::<u64>();
+    unsafe {
+        ptr.write_volatile(0xdead_beef);
+        assert_eq!(ptr.read_volatile(), 0xdead_beef);
+    }
+    // pages mapped into the active space are usable right away
+    let third = Page::containing_address(VirtAddr::new(USER_START + 2 * PAGE));
+    space.map_user_pages(Page::range(third, third + 1), Flags::WRITABLE).unwrap();
+    unsafe { VirtAddr::new(USER_START + 2 * PAGE).as_mut_ptr::<u64>().write_volatile(1) };
+
+    unsafe { address_space::activate_kernel() };
+    assert!(!space.is_active());
+    let kernel_phys = KERNEL_MEMORY.lock().as_ref().unwrap().mapper.translate_addr(VirtAddr::new(USER_START));
+    assert_eq!(kernel_phys, None);
+}
+
+#[test_case]
+fn spaces_are_isolated() {
+    let mut first = AddressSpace::new().unwrap();
+    let mut second = AddressSpace::new().unwrap();
+    first.map_user_pages(user_pages(1), Flags::WRITABLE).unwrap();
+    second.map_user_pages(user_pages(1), Flags::WRITABLE).unwrap();
+    let ptr = VirtAddr::new(USER_START).as_mut_ptr::<u64>();
+
+    unsafe {
+        first.activate();
+        ptr.write_volatile(1);
+        second.activate();
+        ptr.write_volatile(2);
+        first.activate();
+        assert_eq!(ptr.read_volatile(), 1);
+        address_space::activate_kernel();
+    }
+}
+
+#[test_case]
+fn dropping_frees_every_frame() {
+    let before = free_frames();
+    let mut space = AddressSpace::new().unwrap();
+    space.map_user_pages(user_pages(16), Flags::WRITABLE).unwrap();
+    // far away from the first pages, so it needs tables of its own
+    let far = Page::containing_address(VirtAddr::new(USER_START + 0x80_0000_0000));
+    space.map_user_pages(Page::range(far, far + 1), Flags::empty()).unwrap();
+    assert!(free_frames() < before);
+
+    drop(space);
+    assert_eq!(free_frames(), before);
+}
+
+#[test_case]
+fn new_kernel_entries_show_up_in_the_active_space() {
+    // a level 4 entry the kernel doesn't use, between the user part and the heap
+    let addr = VirtAddr::new(0x_4200_0000_0000);
+    let page: Page<Size4KiB> = Page::containing_address(addr);
+    let flags = Flags::PRESENT | Flags::WRITABLE | Flags::NO_EXECUTE;
+    let mut space = AddressSpace::new().unwrap();
+    assert_eq!(space.mapper().translate_addr(addr), None);
+
+    unsafe { space.activate() };
+    KERNEL_MEMORY.lock().as_mut().unwrap().map_pages(Page::range(page, page + 1), flags).unwrap();
+    // the first access faults and the handler copies the new entry over
+    let ptr = addr.as_mut_ptr::<u64>();
+    unsafe {
+        ptr.write_volatile(42);
+        assert_eq!(ptr.read_volatile(), 42);
+        address_space::activate_kernel();
+    }
+
+    let kernel_phys = KERNEL_MEMORY.lock().as_ref().unwrap().mapper.translate_addr(addr);
+    assert_eq!(space.mapper().translate_addr(addr), kernel_phys);
+    KERNEL_MEMORY.lock().as_mut().unwrap().unmap_range(addr, Size4KiB::SIZE).unwrap();
+}
@@ -0,0 +1,211 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oubre_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{
+    bootinfo::{
+        MemoryMap,
+        MemoryRegionType,
+    },
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use spin::{Mutex, MutexGuard};
+
+use oubre_os::memory::{
+    self,
+    report,
+    bitmap::BitmapFrameAllocator,
+};
+use x86_64::{
+    structures::paging::{
+        FrameAllocator,
+        FrameDeallocator,
+        Mapper,
+        Page,
+        PageTableFlags,
+        PhysFrame,
+        Size2MiB,
+    },
+    PhysAddr,
+    VirtAddr,
+};
+
+lazy_static! {
+    // the test cases have no access to the boot info, so main stashes it here
+    static ref BOOT_INFO: Mutex<Option<&'static BootInfo>> = Mutex::new(None);
+    // the bitmap lives in usable memory, so all tests share a single allocator
+    static ref FRAME_ALLOCATOR: Mutex<Option<BitmapFrameAllocator>> = Mutex::new(None);
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    BOOT_INFO.lock().replace(boot_info);
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    FRAME_ALLOCATOR.lock().replace(frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oubre_os::test_panic_handler(info);
+}
+
+fn memory_map() -> &'static MemoryMap {
+    let boot_info = BOOT_INFO.lock().expect("boot info not set");
+    &boot_info.memory_map
+}
+
+fn phys_mem_offset() -> VirtAddr {
+    let boot_info = BOOT_INFO.lock().expect("boot info not set");
+    VirtAddr::new(boot_info.physical_memory_offset)
+}
+
+fn is_usable(frame: PhysFrame) -> bool {
+    report::is_usable(memory_map(), frame)
+}
+
+fn frame_allocator() -> MutexGuard<'static, Option<BitmapFrameAllocator>> {
+    FRAME_ALLOCATOR.lock()
+}
+
+#[test_case]
+fn bitmap_never_hands_out_reserved_frames() {
+    let mut guard = frame_allocator();
+    let frame_allocator = guard.as_mut().unwrap();
+    let free_before = frame_allocator.free_frames();
+
+    // each frame holds the address of the one before, so all of them can be given back
+    let mut last = None;
+    while let Some(frame) = frame_allocator.allocate_frame() {
+        assert!(is_usable(frame), "{:?} is reserved", frame);
+        let link = last.map_or(u64::MAX, |last: PhysFrame| last.start_address().as_u64());
+        unsafe { (phys_mem_offset() + frame.start_address().as_u64()).as_mut_ptr::<u64>().write(link) };
+        last = Some(frame);
+    }
+    assert_eq!(frame_allocator.free_frames(), 0);
+
+    while let Some(frame) = last {
+        let link = unsafe { (phys_mem_offset() + frame.start_address().as_u64()).as_ptr::<u64>().read() };
+        unsafe { frame_allocator.deallocate_frame(frame) };
+        last = (link != u64::MAX).then(|| PhysFrame::containing_address(PhysAddr::new(link)));
+    }
+    assert_eq!(frame_allocator.free_frames(), free_before);
+}
+
+#[test_case]
+fn bitmap_only_takes_back_usable_frames() {
+    let mut guard = frame_allocator();
+    let frame_allocator = guard.as_mut().unwrap();
+    let frame = frame_allocator.allocate_frame().expect("out of frames");
+    assert!(frame_allocator.is_allocatable(frame));
+    unsafe { frame_allocator.deallocate_frame(frame) };
+
+    // used for good, though the bitmap marks them used just like allocated frames
+    let vga_buffer = PhysFrame::containing_address(PhysAddr::new(0xb8000));
+    assert!(!frame_allocator.is_free(vga_buffer) && !frame_allocator.is_allocatable(vga_buffer));
+    let kernel = memory_map()
+        .iter()
+        .find(|r| r.region_type == MemoryRegionType::Kernel)
+        .expect("no kernel region");
+    let kernel_frame = PhysFrame::containing_address(PhysAddr::new(kernel.range.start_addr()));
+    assert!(!frame_allocator.is_allocatable(kernel_frame));
+}
+
+#[test_case]
+fn bitmap_reuses_freed_frames() {
+    let mut guard = frame_allocator();
+    let frame_allocator = guard.as_mut().unwrap();
+    let free_before = frame_allocator.free_frames();
+
+    let mut frames = [None; 1000];
+    for slot in frames.iter_mut() {
+        let frame = frame_allocator.allocate_frame().expect("out of frames");
+        assert!(is_usable(frame));
+        assert!(!frame_allocator.is_free(frame));
+        *slot = Some(frame);
+    }
+    assert_eq!(frame_allocator.free_frames(), free_before - frames.len());
+
+    for frame in frames.iter().flatten() {
+        unsafe { frame_allocator.deallocate_frame(*frame) };
+        assert!(frame_allocator.is_free(*frame));
+    }
+    assert_eq!(frame_allocator.free_frames(), free_before);
+
+    // the lowest free frames get handed out first, so the same frames come back
+    for frame in frames.iter().flatten() {
+        assert_eq!(frame_allocator.allocate_frame(), Some(*frame));
+    }
+    for frame in frames.iter().flatten() {
+        unsafe { frame_allocator.deallocate_frame(*frame) };
+    }
+}
+
+#[test_case]
+fn bitmap_never_exhausts_with_frees() {
+    let mut guard = frame_allocator();
+    let frame_allocator = guard.as_mut().unwrap();
+    let free_frames = frame_allocator.free_frames();
+    // without deallocation this would run out of frames halfway through
+    for _ in 0..free_frames * 2 {
+        let frame = frame_allocator.allocate_frame().expect("frame leaked");
+        unsafe { frame_allocator.deallocate_frame(frame) };
+    }
+    assert_eq!(frame_allocator.free_frames(), free_frames);
+}
+
+#[test_case]
+fn bitmap_hands_out_contiguous_huge_frames() {
+    let mut guard = frame_allocator();
+    let frame_allocator = guard.as_mut().unwrap();
+    let free_before = frame_allocator.free_frames();
+
+    let huge: PhysFrame<Size2MiB> = frame_allocator.allocate_frame_of().expect("no 2MiB of free frames");
+    let first = PhysFrame::containing_address(huge.start_address());
+    // all 512 frames are usable and taken
+    for frame in PhysFrame::range(first, first + 512) {
+        assert!(is_usable(frame));
+        assert!(!frame_allocator.is_free(frame));
+    }
+    assert_eq!(frame_allocator.free_frames(), free_before - 512);
+
+    unsafe { frame_allocator.deallocate_frame_of(huge) };
+    assert_eq!(frame_allocator.free_frames(), free_before);
+    assert_eq!(frame_allocator.allocate_frame_of::<Size2MiB>(), Some(huge));
+    unsafe { frame_allocator.deallocate_frame_of(huge) };
+}
+
+#[test_case]
+fn unmapped_frames_go_back() {
+    let mut mapper = unsafe { memory::init(phys_mem_offset()) };
+    let mut guard = frame_allocator();
+    let frame_allocator = guard.as_mut().unwrap();
+
+    // the page tables created for it come from the same allocator, so they stay in use
+    let page: Page = Page::containing_address(VirtAddr::new(0x_5555_0000_0000));
+    let frame = frame_allocator.allocate_frame().expect("out of frames");
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    unsafe {
+        mapper.map_to(page, frame, flags, frame_allocator)
+            .expect("map_to failed")
+            .flush();
+    }
+    let free_mapped = frame_allocator.free_frames();
+
+    let (unmapped, flush) = mapper.unmap(page).expect("unmap failed");
+    flush.flush();
+    assert_eq!(unmapped, frame);
+    unsafe { frame_allocator.deallocate_frame(unmapped) };
+    assert_eq!(frame_allocator.free_frames(), free_mapped + 1);
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+use x86_64::{
+    structures::paging::PhysFrame,
+    VirtAddr,
+};
+
+use oubre_os::{
+    exit_qemu,
+    memory::buddy::BuddyFrameAllocator,
+    QemuExitCode,
+    serial_print,
+    serial_println,
+};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("buddy_double_free::double_free_inside_free_block...\t");
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut frame_allocator = unsafe {
+        BuddyFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    let block = frame_allocator.allocate(1).expect("out of frames");
+    let second = PhysFrame::containing_address(block.start_address() + 4096u64);
+    unsafe {
+        frame_allocator.deallocate(block, 1);
+        // the second frame is no free block of its own, it lies inside the one
+        // freed above (or one it merged into)
+        frame_allocator.deallocate(second, 0);
+    }
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
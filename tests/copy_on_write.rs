@@ -0,0 +1,152 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oubre_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+
+use oubre_os::{
+    allocator,
+    interrupts,
+    memory::{
+        self,
+        bitmap::BitmapFrameAllocator,
+        cow::{COPY_ON_WRITE, SHARED_FRAMES},
+        vma::{Region, RegionKind, VIRTUAL_REGIONS},
+        KernelMemory,
+        KERNEL_MEMORY,
+    },
+};
+use x86_64::{
+    structures::paging::{
+        mapper::{
+            MappedFrame,
+            TranslateResult,
+        },
+        PageTableFlags as Flags,
+        PhysFrame,
+        Translate,
+    },
+    VirtAddr,
+};
+
+const PAGE: u64 = 4096;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+    // copies are made in the page fault handler
+    interrupts::init_idt();
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oubre_os::test_panic_handler(info);
+}
+
+fn with_kernel_memory<R>(f: impl FnOnce(&mut KernelMemory) -> R) -> R {
+    f(KERNEL_MEMORY.lock().as_mut().expect("kernel memory not initialized"))
+}
+
+fn mapping(addr: VirtAddr) -> (PhysFrame, Flags) {
+    with_kernel_memory(|kernel_memory| match kernel_memory.mapper.translate(addr) {
+        TranslateResult::Mapped { frame: MappedFrame::Size4KiB(frame), flags, .. } => (frame, flags),
+        _ => panic!("{:?} is not mapped", addr),
+    })
+}
+
+fn free_frames() -> usize {
+    with_kernel_memory(|kernel_memory| kernel_memory.frame_allocator.free_frames())
+}
+
+// maps a page holding 'value' and shares it copy-on-write with a second page
+fn shared_pages(value: u64) -> (Region, Region) {
+    let mut regions = VIRTUAL_REGIONS.lock();
+    let src = regions.allocate(PAGE, RegionKind::Other, "cow source").unwrap();
+    let dst = regions.allocate(PAGE, RegionKind::Other, "cow copy").unwrap();
+    drop(regions);
+    with_kernel_memory(|kernel_memory| {
+        kernel_memory.map_range(src.start, PAGE, Flags::PRESENT | Flags::WRITABLE).unwrap();
+        unsafe { src.start.as_mut_ptr::<u64>().write_volatile(value) };
+        kernel_memory.map_copy_on_write(src.start, dst.start, PAGE).unwrap();
+    });
+    (src, dst)
+}
+
+fn unmap(regions: &[Region]) {
+    for region in regions {
+        with_kernel_memory(|kernel_memory| kernel_memory.unmap_range(region.start, region.size).unwrap());
+        VIRTUAL_REGIONS.lock().release(region.start);
+    }
+}
+
+#[test_case]
+fn shared_pages_are_read_only() {
+    let (src, dst) = shared_pages(7);
+
+    let (src_frame, src_flags) = mapping(src.start);
+    let (dst_frame, dst_flags) = mapping(dst.start);
+    assert_eq!(src_frame, dst_frame);
+    for flags in [src_flags, dst_flags].iter() {
+        assert!(flags.contains(COPY_ON_WRITE));
+        assert!(!flags.contains(Flags::WRITABLE));
+    }
+    assert_eq!(SHARED_FRAMES.lock().owners(src_frame), 2);
+    assert_eq!(unsafe { dst.start.as_ptr::<u64>().read_volatile() }, 7);
+
+    unmap(&[src, dst]);
+}
+
+#[test_case]
+fn writes_get_a_private_copy() {
+    let (src, dst) = shared_pages(7);
+    let (original, _) = mapping(src.start);
+
+    unsafe { dst.start.as_mut_ptr::<u64>().write_volatile(8) };
+    let (copy, flags) = mapping(dst.start);
+    assert_ne!(copy, original);
+    assert!(flags.contains(Flags::WRITABLE) && !flags.contains(COPY_ON_WRITE));
+    assert_eq!(unsafe { src.start.as_ptr::<u64>().read_volatile() }, 7);
+    assert_eq!(unsafe { dst.start.as_ptr::<u64>().read_volatile() }, 8);
+
+    // the last owner keeps the original frame
+    assert_eq!(SHARED_FRAMES.lock().owners(original), 1);
+    unsafe { src.start.as_mut_ptr::<u64>().write_volatile(9) };
+    assert_eq!(mapping(src.start).0, original);
+    assert!(mapping(src.start).1.contains(Flags::WRITABLE));
+
+    unmap(&[src, dst]);
+}
+
+#[test_case]
+fn shared_frames_are_freed_with_the_last_mapping() {
+    let before = free_frames();
+    let (src, dst) = shared_pages(7);
+    let shared = free_frames();
+
+    // the frame stays in use by the copy
+    unmap(&[src]);
+    assert_eq!(free_frames(), shared);
+    assert_eq!(unsafe { dst.start.as_ptr::<u64>().read_volatile() }, 7);
+
+    unmap(&[dst]);
+    assert_eq!(free_frames(), shared + 1);
+    assert!(free_frames() <= before);
+}
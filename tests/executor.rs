@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oubre_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::alloc::{dealloc, Layout};
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
+use core::{
+    panic::PanicInfo,
+    ptr::{self, null_mut},
+};
+
+use oubre_os::{
+    allocator::{self, HEAP_MAX_SIZE},
+    memory::{
+        self,
+        bitmap::BitmapFrameAllocator,
+    },
+    task::{
+        executor::{Executor, SpawnError},
+        Task,
+    },
+};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oubre_os::test_panic_handler(info);
+}
+
+#[test_case]
+fn spawning_fails_when_the_queue_is_full() {
+    let mut executor = Executor::new();
+    let mut spawned = 0;
+    let err = loop {
+        let task = Task::try_new(async {}).expect("out of memory");
+        match executor.try_spawn(task) {
+            Ok(()) => spawned += 1,
+            Err(err) => break err,
+        }
+    };
+    assert_eq!(err, SpawnError::QueueFull);
+    assert_eq!(spawned, 100);
+}
+
+#[test_case]
+fn creating_a_task_fails_when_the_heap_is_full() {
+    let buffer = [1u64; 64];
+    let future = async move {
+        assert_eq!(buffer[0], 1);
+    };
+    let layout = Layout::for_value(&future);
+    assert!(layout.size() >= 8 && layout.align() >= 8);
+
+    // fill the heap with blocks of the future's size, each one points to the one before
+    allocator::set_heap_max_size(allocator::heap_stats().heap_size);
+    let mut last: *mut u8 = null_mut();
+    while let Ok(block) = allocator::try_alloc(layout) {
+        unsafe { (block.as_ptr() as *mut *mut u8).write(last) };
+        last = block.as_ptr();
+    }
+
+    let result = Task::try_new(future);
+
+    while !last.is_null() {
+        let block = last;
+        unsafe {
+            last = ptr::read(block as *mut *mut u8);
+            dealloc(block, layout);
+        }
+    }
+    allocator::set_heap_max_size(HEAP_MAX_SIZE);
+
+    let err = result.err().expect("the task was created on a full heap");
+    assert_eq!(err.layout, Some(layout));
+    // with memory available again both succeed
+    let mut executor = Executor::new();
+    executor.try_spawn(Task::try_new(async {}).unwrap()).unwrap();
+}
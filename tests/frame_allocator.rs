@@ -0,0 +1,210 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oubre_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{
+    bootinfo::{
+        MemoryMap,
+        MemoryRegionType,
+    },
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use spin::{Mutex, MutexGuard};
+
+use oubre_os::memory::{
+    report::{self, MemorySummary},
+    BootInfoFrameAllocator,
+    buddy::BuddyFrameAllocator,
+};
+use x86_64::{
+    structures::paging::{
+        FrameAllocator,
+        FrameDeallocator,
+        PhysFrame,
+        Size2MiB,
+    },
+    PhysAddr,
+    VirtAddr,
+};
+
+lazy_static! {
+    // the test cases have no access to the boot info, so main stashes it here
+    static ref BOOT_INFO: Mutex<Option<&'static BootInfo>> = Mutex::new(None);
+    // the buddy allocator keeps its metadata in usable memory, so all tests share one
+    static ref BUDDY: Mutex<Option<BuddyFrameAllocator>> = Mutex::new(None);
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    BOOT_INFO.lock().replace(boot_info);
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let frame_allocator = unsafe {
+        BuddyFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    BUDDY.lock().replace(frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oubre_os::test_panic_handler(info);
+}
+
+fn memory_map() -> &'static MemoryMap {
+    let boot_info = BOOT_INFO.lock().expect("boot info not set");
+    &boot_info.memory_map
+}
+
+fn phys_mem_offset() -> VirtAddr {
+    let boot_info = BOOT_INFO.lock().expect("boot info not set");
+    VirtAddr::new(boot_info.physical_memory_offset)
+}
+
+fn is_usable(frame: PhysFrame) -> bool {
+    report::is_usable(memory_map(), frame)
+}
+
+fn buddy() -> MutexGuard<'static, Option<BuddyFrameAllocator>> {
+    BUDDY.lock()
+}
+
+#[test_case]
+fn summary_matches_the_memory_map() {
+    let summary = MemorySummary::new(memory_map());
+    let usable_frames: u64 = memory_map()
+        .iter()
+        .filter(|r| r.region_type == MemoryRegionType::Usable)
+        .map(|r| r.range.end_frame_number - r.range.start_frame_number)
+        .sum();
+    assert_eq!(summary.usable, usable_frames * 4096);
+    assert!(summary.kernel > 0);
+    assert!(summary.page_tables > 0);
+    assert_eq!(summary.regions, memory_map().len());
+
+    // the kernel's own code is never usable
+    let kernel = memory_map().iter().find(|r| r.region_type == MemoryRegionType::Kernel).unwrap();
+    let code = PhysFrame::containing_address(PhysAddr::new(kernel.range.start_addr()));
+    assert!(!is_usable(code));
+    assert_eq!(report::region_of(memory_map(), code.start_address()), Some(kernel));
+}
+
+#[test_case]
+fn thousands_of_frames() {
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(memory_map()) };
+    let mut previous = PhysAddr::new(0);
+    for i in 0..10_000 {
+        let frame = frame_allocator.allocate_frame().expect("out of frames");
+        // the memory map is sorted, so frames must come out strictly increasing
+        // which also means no frame is handed out twice
+        if i > 0 {
+            assert!(frame.start_address() > previous);
+        }
+        assert!(is_usable(frame));
+        previous = frame.start_address();
+    }
+}
+
+#[test_case]
+fn all_usable_frames() {
+    let usable_frames: u64 = memory_map()
+        .iter()
+        .filter(|r| r.region_type == MemoryRegionType::Usable)
+        .map(|r| r.range.end_frame_number - r.range.start_frame_number)
+        .sum();
+
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(memory_map()) };
+    let mut allocated = 0;
+    while frame_allocator.allocate_frame().is_some() {
+        allocated += 1;
+    }
+    assert_eq!(allocated, usable_frames);
+    // once exhausted, the allocator stays exhausted
+    assert!(frame_allocator.allocate_frame().is_none());
+}
+
+#[test_case]
+fn buddy_blocks_are_aligned() {
+    let mut guard = buddy();
+    let frame_allocator = guard.as_mut().unwrap();
+    let stats_before = frame_allocator.stats();
+
+    let mut blocks = [None; 10];
+    for (order, slot) in blocks.iter_mut().enumerate() {
+        let frame = frame_allocator.allocate(order).expect("out of frames");
+        let block_size = 4096u64 << order;
+        assert_eq!(frame.start_address().as_u64() % block_size, 0);
+        // every frame of the block must be usable
+        let last = PhysFrame::containing_address(frame.start_address() + (block_size - 4096));
+        assert!(is_usable(frame) && is_usable(last));
+        *slot = Some(frame);
+    }
+    let huge: PhysFrame<Size2MiB> = frame_allocator.allocate_frame().expect("no 2MiB block");
+    assert!(is_usable(PhysFrame::containing_address(huge.start_address())));
+
+    unsafe {
+        frame_allocator.deallocate_frame(huge);
+        for (order, frame) in blocks.iter().enumerate() {
+            frame_allocator.deallocate(frame.unwrap(), order);
+        }
+    }
+    assert_eq!(frame_allocator.stats(), stats_before);
+}
+
+#[test_case]
+fn buddy_coalesces_on_free() {
+    let mut guard = buddy();
+    let frame_allocator = guard.as_mut().unwrap();
+    let stats_before = frame_allocator.stats();
+
+    // chop a big block into single frames ...
+    let mut frames = [None; 1024];
+    for slot in frames.iter_mut() {
+        *slot = frame_allocator.allocate(0);
+    }
+    assert_eq!(frame_allocator.free_frames(), stats_before.free_frames - frames.len());
+    // ... and free them in an interleaved order, buddies have to merge again
+    for frame in frames.iter().step_by(2).chain(frames.iter().skip(1).step_by(2)) {
+        unsafe { frame_allocator.deallocate(frame.expect("out of frames"), 0) };
+    }
+    assert_eq!(frame_allocator.stats(), stats_before);
+}
+
+#[test_case]
+fn buddy_reports_fragmentation() {
+    let mut guard = buddy();
+    let frame_allocator = guard.as_mut().unwrap();
+    let stats_before = frame_allocator.stats();
+
+    // grab everything in pairs and give back every other frame, each kept frame
+    // holds the address of the one before, so all of them can be given back
+    let mut kept = 0;
+    let mut last = None;
+    while let Some(frame) = frame_allocator.allocate(1) {
+        let buddy = PhysFrame::containing_address(frame.start_address() + 4096u64);
+        unsafe { frame_allocator.deallocate(buddy, 0) };
+        let link = last.map_or(u64::MAX, |last: PhysFrame| last.start_address().as_u64());
+        unsafe { (phys_mem_offset() + frame.start_address().as_u64()).as_mut_ptr::<u64>().write(link) };
+        last = Some(frame);
+        kept += 1;
+    }
+    let stats = frame_allocator.stats();
+    assert!(stats.free_frames >= kept);
+    assert_eq!(stats.largest_free_order(), Some(0));
+    assert!(stats.fragmentation() > 90);
+    assert!(frame_allocator.allocate(1).is_none());
+
+    while let Some(frame) = last {
+        let link = unsafe { (phys_mem_offset() + frame.start_address().as_u64()).as_ptr::<u64>().read() };
+        unsafe { frame_allocator.deallocate(frame, 0) };
+        last = (link != u64::MAX).then(|| PhysFrame::containing_address(PhysAddr::new(link)));
+    }
+    assert_eq!(frame_allocator.stats(), stats_before);
+}
@@ -13,10 +13,10 @@ use bootloader::{
 use core::panic::PanicInfo;
 
 use oubre_os::{
-    allocator::{self, HEAP_SIZE},
+    allocator::{self, HEAP_SIZE, HEAP_MAX_SIZE},
     memory::{
         self,
-        BootInfoFrameAllocator,
+        bitmap::BitmapFrameAllocator,
     },
 };
 use x86_64::VirtAddr;
@@ -28,11 +28,12 @@ fn main(boot_info: &'static BootInfo) -> ! {
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
     let mut frame_allocator = unsafe {
-        BootInfoFrameAllocator::init(&boot_info.memory_map)
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
     };
 
     allocator::init_heap(&mut mapper, &mut frame_allocator)
         .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
 
     test_main();
     loop {}
@@ -82,4 +83,357 @@ fn many_boxes_lived() {
         assert_eq!(*x, i);
     }
     assert_eq!(*long_lived, 1);
-}
\ No newline at end of file
+}
+
+// the external allocator has a fixed size
+#[cfg(not(feature = "alloc-external"))]
+#[test_case]
+fn heap_grows() {
+    use alloc::vec::Vec;
+    // far more than the initially mapped HEAP_SIZE
+    let n = HEAP_SIZE;
+    let mut vec = Vec::with_capacity(n);
+    for i in 0..n {
+        vec.push(i);
+    }
+    assert_eq!(vec.iter().sum::<usize>(), (n - 1) * n / 2);
+}
+
+#[test_case]
+fn heap_growth_is_limited() {
+    use alloc::alloc::{alloc, Layout};
+    let layout = Layout::from_size_align(HEAP_MAX_SIZE, 8).unwrap();
+    let ptr = unsafe { alloc(layout) };
+    assert!(ptr.is_null());
+}
+
+// the external allocator keeps no counters, the debug allocator adds guards to every size
+#[cfg(not(any(feature = "alloc-external", feature = "alloc-debug")))]
+#[test_case]
+fn stats_track_allocations() {
+    let before = allocator::heap_stats();
+    let x = Box::new([1u8; 100]);
+    let during = allocator::heap_stats();
+    assert_eq!(during.live_allocations(), before.live_allocations() + 1);
+    assert_eq!(during.bytes_in_use, before.bytes_in_use + 100);
+    assert!(during.peak_bytes_in_use.unwrap() >= during.bytes_in_use);
+    assert!(during.free_bytes <= during.heap_size);
+    drop(x);
+
+    let after = allocator::heap_stats();
+    assert_eq!(after.bytes_in_use, before.bytes_in_use);
+    assert_eq!(after.deallocations, before.deallocations + 1);
+    assert_eq!(after.peak_bytes_in_use, during.peak_bytes_in_use);
+}
+
+#[cfg(all(feature = "alloc-fsb", not(feature = "alloc-debug")))]
+#[test_case]
+fn stats_count_block_classes() {
+    let class = |stats: allocator::stats::HeapStats| {
+        *stats.block_classes.iter().find(|c| c.block_size == 128).unwrap()
+    };
+    let before = class(allocator::heap_stats());
+    let x = Box::new([1u8; 100]);
+    assert_eq!(class(allocator::heap_stats()).blocks_in_use, before.blocks_in_use + 1);
+    drop(x);
+    let after = class(allocator::heap_stats());
+    assert_eq!(after.blocks_in_use, before.blocks_in_use);
+    assert!(after.free_blocks >= 1);
+}
+
+#[cfg(all(feature = "alloc-bump", not(feature = "alloc-debug")))]
+#[test_case]
+fn bump_reclaims_latest_allocation() {
+    let before = allocator::heap_stats();
+    let kept = Box::new([1u8; 64]);
+    let during = allocator::heap_stats();
+    drop(Box::new([2u8; 512]));
+    // the freed box was on top, so its memory is free again
+    assert_eq!(allocator::heap_stats().free_bytes, during.free_bytes);
+    drop(kept);
+    assert!(allocator::heap_stats().free_bytes >= before.free_bytes);
+}
+
+// the external allocator can't grow, so there is nothing to retry
+#[cfg(not(feature = "alloc-external"))]
+#[test_case]
+fn oom_handler_makes_room_for_retry() {
+    use alloc::alloc::{alloc, dealloc, Layout};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use oubre_os::allocator::oom;
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    fn give_up(_layout: Layout) -> bool {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+    // stands in for a cache being shrunk, it lifts the heap limit again
+    fn make_room(_layout: Layout) -> bool {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+        allocator::set_heap_max_size(HEAP_MAX_SIZE);
+        true
+    }
+
+    let layout = Layout::from_size_align(2 * 1024 * 1024, 8).unwrap();
+    allocator::set_heap_max_size(allocator::heap_stats().heap_size + 1024 * 1024);
+    oom::set_oom_handler(Some(give_up));
+    assert!(unsafe { alloc(layout) }.is_null());
+    assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+
+    oom::set_oom_handler(Some(make_room));
+    let ptr = unsafe { alloc(layout) };
+    oom::set_oom_handler(None);
+    assert!(!ptr.is_null());
+    assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+    unsafe { dealloc(ptr, layout) };
+}
+
+#[test_case]
+fn fallible_allocations_report_exhaustion() {
+    use alloc::alloc::{dealloc, Layout};
+
+    let boxed = allocator::try_box([7u8; 100]).expect("out of memory");
+    assert_eq!(boxed[99], 7);
+    let mut vec = allocator::try_vec_with_capacity::<u64>(100).expect("out of memory");
+    assert_eq!(vec.capacity(), 100);
+    vec.push(1);
+
+    // more than the heap may ever grow to
+    let layout = Layout::from_size_align(HEAP_MAX_SIZE, 8).unwrap();
+    let err = allocator::try_alloc(layout).unwrap_err();
+    assert_eq!(err.layout, Some(layout));
+    assert!(allocator::try_vec_with_capacity::<u8>(HEAP_MAX_SIZE).is_err());
+    assert!(allocator::try_vec_with_capacity::<u64>(usize::MAX).is_err());
+
+    let ptr = allocator::try_alloc(Layout::new::<u64>()).expect("out of memory");
+    unsafe { dealloc(ptr.as_ptr(), Layout::new::<u64>()) };
+}
+
+#[test_case]
+fn guard_pages_are_recognized() {
+    use oubre_os::{allocator::HEAP_START, gdt, interrupts::guard_page_fault};
+
+    let heap_end = allocator::heap_stats().heap_size + HEAP_START;
+    assert_eq!(guard_page_fault(VirtAddr::new(HEAP_START as u64 - 1)), Some("heap overflow"));
+    assert_eq!(guard_page_fault(VirtAddr::new(heap_end as u64)), Some("heap overflow"));
+    assert_eq!(guard_page_fault(VirtAddr::new(HEAP_START as u64)), None);
+    let stack_guard = VirtAddr::new(gdt::DOUBLE_FAULT_STACK_START - 8);
+    assert_eq!(guard_page_fault(stack_guard), Some("IST stack overflow"));
+}
+
+#[repr(align(4096))]
+#[allow(dead_code)] // only accessed through raw pointers
+struct Arena([u8; HEAP_SIZE]);
+
+// backing memory for allocators tested outside of the global heap
+static mut ARENA: Arena = Arena([0; HEAP_SIZE]);
+
+#[test_case]
+fn linked_list_coalesces_free_regions() {
+    use alloc::alloc::{GlobalAlloc, Layout};
+    use core::ptr::{self, null_mut};
+    use oubre_os::allocator::{
+        linked_list::LinkedListAllocator,
+        stats::HeapStatistics,
+        Locked,
+    };
+
+    let allocator = Locked::new(LinkedListAllocator::new());
+    unsafe { allocator.lock().init(ptr::addr_of_mut!(ARENA) as usize, HEAP_SIZE) };
+    let layout = |size, align| Layout::from_size_align(size, align).unwrap();
+
+    // fill the heap with blocks of mixed sizes and alignments
+    let mut blocks = [(null_mut(), layout(1, 1)); 128];
+    for (i, block) in blocks.iter_mut().enumerate() {
+        let block_layout = layout(24 + (i % 7) * 96, 8 << (i % 4));
+        let ptr = unsafe { allocator.alloc(block_layout) };
+        assert!(!ptr.is_null());
+        *block = (ptr, block_layout);
+    }
+    // free every other block, refill the holes with small blocks, then free everything
+    for &(ptr, block_layout) in blocks.iter().step_by(2) {
+        unsafe { allocator.dealloc(ptr, block_layout) };
+    }
+    let mut small = [null_mut(); 64];
+    for ptr in small.iter_mut() {
+        *ptr = unsafe { allocator.alloc(layout(16, 8)) };
+        assert!(!ptr.is_null());
+    }
+    for &(ptr, block_layout) in blocks.iter().skip(1).step_by(2) {
+        unsafe { allocator.dealloc(ptr, block_layout) };
+    }
+    for &ptr in small.iter().rev() {
+        unsafe { allocator.dealloc(ptr, layout(16, 8)) };
+    }
+
+    // everything merged back into a single region
+    assert_eq!(allocator.lock().stats().largest_free_region, Some(HEAP_SIZE));
+    let ptr = unsafe { allocator.alloc(layout(HEAP_SIZE, 8)) };
+    assert!(!ptr.is_null());
+}
+
+#[cfg(feature = "alloc-external")]
+#[test_case]
+fn external_peak_is_unknown() {
+    let x = Box::new([1u8; 100]);
+    let stats = allocator::heap_stats();
+    assert!(stats.bytes_in_use >= 100);
+    assert_eq!(stats.peak_bytes_in_use, None);
+    drop(x);
+}
+
+#[cfg(all(feature = "alloc-fsb", not(feature = "alloc-debug")))]
+#[test_case]
+fn empty_slabs_are_released() {
+    use alloc::vec::Vec;
+    use oubre_os::allocator::fixed_size_block::BLOCK_SIZES;
+    let class = |size| BLOCK_SIZES.iter().position(|&s| s == size).unwrap();
+    let before = allocator::heap_stats();
+
+    let small: Vec<Box<[u8; 32]>> = (0..2000).map(|_| Box::new([0; 32])).collect();
+    let during = allocator::heap_stats();
+    assert!(during.block_classes[class(32)].slabs > before.block_classes[class(32)].slabs);
+    drop(small);
+    let after = allocator::heap_stats();
+    // at most one empty slab is kept for the next allocation
+    assert!(after.block_classes[class(32)].slabs <= before.block_classes[class(32)].slabs + 1);
+
+    // the released slabs are reused by another block size without growing the heap
+    let other: Vec<Box<[u8; 64]>> = (0..500).map(|_| Box::new([0; 64])).collect();
+    assert_eq!(allocator::heap_stats().heap_size, during.heap_size);
+    drop(other);
+}
+
+#[test_case]
+fn slab_cache_reuses_constructed_objects() {
+    use alloc::vec::Vec;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use oubre_os::allocator::slab_cache::SlabCache;
+
+    static CONSTRUCTED: AtomicUsize = AtomicUsize::new(0);
+    struct Node {
+        value: u64,
+        _links: [usize; 4],
+    }
+    fn new_node() -> Node {
+        CONSTRUCTED.fetch_add(1, Ordering::Relaxed);
+        Node { value: 7, _links: [0; 4] }
+    }
+
+    let cache = SlabCache::new("node", new_node);
+    let mut nodes: Vec<_> = (0..100).map(|_| cache.alloc().expect("out of memory")).collect();
+    let stats = cache.stats();
+    assert_eq!(stats.objects_in_use, 100);
+    assert_eq!(CONSTRUCTED.load(Ordering::Relaxed), stats.slabs * stats.objects_per_slab);
+    assert!(nodes.iter().all(|node| node.value == 7));
+    for node in nodes.iter_mut() {
+        node.value = 8;
+    }
+
+    // freed objects come back as they were left, without running the constructor
+    let constructed = CONSTRUCTED.load(Ordering::Relaxed);
+    nodes.truncate(50);
+    let again: Vec<_> = (0..50).map(|_| cache.alloc().expect("out of memory")).collect();
+    assert_eq!(CONSTRUCTED.load(Ordering::Relaxed), constructed);
+    assert!(again.iter().all(|node| node.value == 8));
+
+    drop(nodes);
+    drop(again);
+    let stats = cache.stats();
+    assert_eq!((stats.objects_in_use, stats.allocations, stats.frees), (0, 150, 150));
+    assert_eq!(cache.shrink(), stats.slabs);
+    assert_eq!(cache.stats().slabs, 0);
+}
+
+#[test_case]
+fn slab_cache_can_shrink_from_the_oom_handler() {
+    use alloc::alloc::{alloc, dealloc, Layout};
+    use core::{
+        ptr::{self, null_mut},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use oubre_os::allocator::{oom, slab_cache::SlabCache};
+
+    // four objects fill a 4KiB slab
+    static CACHE: SlabCache<[u8; 1000]> = SlabCache::new("buffer", || [0; 1000]);
+    static SHRINKS: AtomicUsize = AtomicUsize::new(0);
+    fn shrink_cache(_layout: Layout) -> bool {
+        SHRINKS.fetch_add(1, Ordering::Relaxed);
+        CACHE.shrink() > 0
+    }
+
+    let buffers: [_; 4] = core::array::from_fn(|_| CACHE.alloc().expect("out of memory"));
+    assert_eq!(CACHE.stats().free_objects, 0);
+
+    // fill the heap with slab sized blocks, each one points to the one before
+    let layout = Layout::from_size_align(4096, 4096).unwrap();
+    allocator::set_heap_max_size(allocator::heap_stats().heap_size);
+    let mut last: *mut u8 = null_mut();
+    loop {
+        let block = unsafe { alloc(layout) };
+        if block.is_null() {
+            break;
+        }
+        unsafe { (block as *mut *mut u8).write(last) };
+        last = block;
+    }
+
+    // there is no room for a new slab, the handler shrinks the very cache that grows
+    oom::set_oom_handler(Some(shrink_cache));
+    assert!(CACHE.alloc().is_none());
+    oom::set_oom_handler(None);
+    assert!(SHRINKS.load(Ordering::Relaxed) > 0);
+
+    while !last.is_null() {
+        let block = last;
+        unsafe {
+            last = ptr::read(block as *mut *mut u8);
+            dealloc(block, layout);
+        }
+    }
+    allocator::set_heap_max_size(HEAP_MAX_SIZE);
+    drop(buffers);
+    assert_eq!(CACHE.shrink(), 1);
+}
+
+#[test_case]
+fn leaks_are_tracked() {
+    use oubre_os::allocator::tracking;
+
+    // the test runner tracks every test, take over for a moment
+    tracking::stop_tracking();
+    tracking::start_tracking("leaks_are_tracked");
+    let kept = Box::new(41);
+    let leaked = Box::leak(Box::new(42));
+    drop(kept);
+    assert_eq!(tracking::stop_tracking(), 1);
+
+    // free it again and hand tracking back to the test runner
+    drop(unsafe { Box::from_raw(leaked) });
+    tracking::start_tracking("leaks_are_tracked");
+}
+
+#[test_case]
+fn leaks_beyond_the_table_are_counted() {
+    use alloc::vec::Vec;
+    use oubre_os::allocator::tracking::{self, MAX_TRACKED};
+
+    let mut boxes = Vec::with_capacity(2 * (MAX_TRACKED + 10));
+    tracking::stop_tracking();
+    tracking::start_tracking("leaks_beyond_the_table_are_counted");
+    for i in 0..MAX_TRACKED + 10 {
+        boxes.push(Box::new(i));
+    }
+    assert_eq!(tracking::stop_tracking(), MAX_TRACKED + 10);
+
+    // the ones freed while tracking are not counted, overflowed or not
+    tracking::start_tracking("leaks_beyond_the_table_are_counted");
+    for i in 0..MAX_TRACKED + 10 {
+        boxes.push(Box::new(i));
+    }
+    boxes.truncate(MAX_TRACKED + 10);
+    assert_eq!(tracking::stop_tracking(), 0);
+
+    drop(boxes);
+    tracking::start_tracking("leaks_beyond_the_table_are_counted");
+}
@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+use oubre_os::{
+    allocator,
+    exit_qemu,
+    memory::{
+        self,
+        bitmap::BitmapFrameAllocator,
+    },
+    serial_print,
+    serial_println,
+    QemuExitCode,
+};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    serial_print!("heap_corruption::callers_of_shallow_frames...\t");
+    // main runs in the topmost page of the kernel stack
+    let callers = allocator::debug::callers();
+    if callers[0] == 0 {
+        serial_println!("[no callers recorded]");
+        exit_qemu(QemuExitCode::Failed);
+    }
+    serial_println!("[ok]");
+
+    serial_print!("heap_corruption::overflow_is_detected...\t");
+    overflow();
+    serial_println!("[corruption not detected]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+fn overflow() {
+    let buffer = Box::new([0u8; 24]);
+    let ptr = Box::into_raw(buffer) as *mut u8;
+    unsafe {
+        // one byte past the end of the allocation
+        ptr.add(24).write(0);
+        drop(Box::from_raw(ptr as *mut [u8; 24]));
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
@@ -0,0 +1,90 @@
+#![no_std]
+#![no_main]
+
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{
+        InterruptDescriptorTable,
+        InterruptStackFrame,
+        PageFaultErrorCode,
+    },
+    VirtAddr,
+};
+
+use oubre_os::{
+    allocator::{
+        self,
+        HEAP_START,
+        HEAP_SIZE,
+    },
+    exit_qemu,
+    interrupts,
+    memory::{
+        self,
+        bitmap::BitmapFrameAllocator,
+    },
+    QemuExitCode,
+    serial_print,
+    serial_println,
+};
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut test_idt = InterruptDescriptorTable::new();
+        test_idt.page_fault.set_handler_fn(test_page_fault_handler);
+        test_idt
+    };
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    _error_code: PageFaultErrorCode,
+) {
+    match interrupts::guard_page_fault(Cr2::read()) {
+        Some("heap overflow") => {
+            serial_println!("[ok]");
+            exit_qemu(QemuExitCode::Success);
+        }
+        other => {
+            serial_println!("[Failed]\n");
+            serial_println!("wrong page fault report: {:?}", other);
+            exit_qemu(QemuExitCode::Failed);
+        }
+    }
+    loop {}
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("heap_overflow::heap_overflow...\t");
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+    TEST_IDT.load();
+
+    // one byte past the end of the heap
+    let heap_end = (HEAP_START + HEAP_SIZE) as *mut u8;
+    unsafe { core::ptr::write_volatile(heap_end, 0) };
+
+    panic!("Execution continued after writing past the heap");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oubre_os::test_panic_handler(info);
+}
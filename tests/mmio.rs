@@ -0,0 +1,113 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oubre_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+
+use oubre_os::{
+    allocator,
+    memory::{
+        self,
+        bitmap::BitmapFrameAllocator,
+        mmio::{self, CacheMode},
+        vma::VIRTUAL_REGIONS,
+        KERNEL_MEMORY,
+    },
+};
+use volatile::Volatile;
+use x86_64::{
+    structures::paging::{
+        mapper::TranslateResult,
+        PageTableFlags as Flags,
+        Translate,
+    },
+    PhysAddr,
+    VirtAddr,
+};
+
+// the VGA text buffer is the one device every test machine has
+const VGA_BUFFER: u64 = 0xb8000;
+const VGA_BUFFER_SIZE: usize = 80 * 25 * 2;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oubre_os::test_panic_handler(info);
+}
+
+fn translate(addr: VirtAddr) -> Option<(PhysAddr, Flags)> {
+    match KERNEL_MEMORY.lock().as_ref().unwrap().mapper.translate(addr) {
+        TranslateResult::Mapped { frame, offset, flags } => Some((frame.start_address() + offset, flags)),
+        _ => None,
+    }
+}
+
+#[test_case]
+fn registers_are_mapped_uncached() {
+    let region = unsafe { mmio::map_mmio(PhysAddr::new(VGA_BUFFER), VGA_BUFFER_SIZE) }.unwrap();
+    let (phys, flags) = translate(region.base()).expect("the region is not mapped");
+    assert_eq!(phys.as_u64(), VGA_BUFFER);
+    assert!(flags.contains(Flags::NO_CACHE | Flags::WRITE_THROUGH | Flags::WRITABLE));
+
+    let framebuffer = unsafe { mmio::map_mmio_with(PhysAddr::new(VGA_BUFFER), VGA_BUFFER_SIZE, CacheMode::WriteThrough) }.unwrap();
+    let (_, flags) = translate(framebuffer.base()).unwrap();
+    assert!(flags.contains(Flags::WRITE_THROUGH) && !flags.contains(Flags::NO_CACHE));
+}
+
+#[test_case]
+fn registers_read_back_what_was_written() {
+    // the last character of the last line
+    let offset = VGA_BUFFER_SIZE - 2;
+    let mut region = unsafe { mmio::map_mmio(PhysAddr::new(VGA_BUFFER), VGA_BUFFER_SIZE) }.unwrap();
+    let saved: u16 = region.read(offset);
+
+    region.write(offset, 0x0f21u16);
+    assert_eq!(region.read::<u16>(offset), 0x0f21);
+    // the same register through the typed accessor
+    let register: &Volatile<u16> = region.register(offset);
+    assert_eq!(register.read(), 0x0f21);
+
+    region.write(offset, saved);
+}
+
+#[test_case]
+fn unaligned_regions_keep_their_offset() {
+    let phys = PhysAddr::new(VGA_BUFFER + 0x10);
+    let region = unsafe { mmio::map_mmio(phys, 16) }.unwrap();
+    assert!(!region.base().is_aligned(4096u64));
+    assert_eq!(translate(region.base()).unwrap().0, phys);
+    assert_eq!(region.len(), 16);
+}
+
+#[test_case]
+fn dropping_unmaps_the_region() {
+    let region = unsafe { mmio::map_mmio(PhysAddr::new(VGA_BUFFER), VGA_BUFFER_SIZE) }.unwrap();
+    let base = region.base();
+    assert!(VIRTUAL_REGIONS.lock().find(base).is_some());
+
+    drop(region);
+    assert_eq!(translate(base), None);
+    assert!(VIRTUAL_REGIONS.lock().find(base).is_none());
+}
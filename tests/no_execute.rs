@@ -0,0 +1,94 @@
+#![no_std]
+#![no_main]
+
+#![feature(abi_x86_interrupt)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use lazy_static::lazy_static;
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{
+        InterruptDescriptorTable,
+        InterruptStackFrame,
+        PageFaultErrorCode,
+    },
+    VirtAddr,
+};
+
+use oubre_os::{
+    allocator,
+    exit_qemu,
+    memory::{
+        self,
+        bitmap::BitmapFrameAllocator,
+    },
+    QemuExitCode,
+    serial_print,
+    serial_println,
+};
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut test_idt = InterruptDescriptorTable::new();
+        test_idt.page_fault.set_handler_fn(test_page_fault_handler);
+        test_idt
+    };
+}
+
+// the heap address that is jumped to
+static TARGET: AtomicU64 = AtomicU64::new(0);
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    let expected = PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::INSTRUCTION_FETCH;
+    if error_code.contains(expected) && Cr2::read().as_u64() == TARGET.load(Ordering::SeqCst) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[Failed]\n");
+        serial_println!("unexpected page fault at {:?}: {:?}", Cr2::read(), error_code);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("no_execute::execute_heap...\t");
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+    TEST_IDT.load();
+
+    // a single 'ret', it would return right away if the heap were executable
+    let code = Box::new([0xc3u8; 16]);
+    TARGET.store(code.as_ptr() as u64, Ordering::SeqCst);
+    let function: extern "C" fn() = unsafe { core::mem::transmute(code.as_ptr()) };
+    function();
+
+    panic!("Execution continued after calling into the heap");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oubre_os::test_panic_handler(info);
+}
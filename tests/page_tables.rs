@@ -0,0 +1,231 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oubre_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+
+use oubre_os::{
+    allocator::{self, HEAP_START},
+    memory::{
+        self,
+        bitmap::BitmapFrameAllocator,
+        inspect::{self, Mapping},
+        protection,
+        vma::{RegionKind, VIRTUAL_REGIONS},
+        KernelMemory,
+        KERNEL_MEMORY,
+    },
+};
+use x86_64::{
+    structures::paging::{
+        Mapper,
+        Page,
+        PageTableFlags as Flags,
+        PhysFrame,
+        PageSize,
+        Size2MiB,
+        Size4KiB,
+        Translate,
+    },
+    PhysAddr,
+    VirtAddr,
+};
+
+const PAGE: u64 = 4096;
+// the frames of the VGA text buffer, mapping them a second time does no harm
+const VGA_FRAMES: u64 = 0xb8000;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oubre_os::test_panic_handler(info);
+}
+
+fn with_kernel_memory<R>(f: impl FnOnce(&mut KernelMemory) -> R) -> R {
+    f(KERNEL_MEMORY.lock().as_mut().expect("kernel memory not initialized"))
+}
+
+fn mappings_in(start: VirtAddr, end: VirtAddr) -> [Option<Mapping>; 4] {
+    let mut found = [None; 4];
+    let mut count = 0;
+    with_kernel_memory(|kernel_memory| unsafe {
+        let phys_offset = kernel_memory.mapper.phys_offset();
+        inspect::for_each_mapping(kernel_memory.mapper.level_4_table(), phys_offset, &mut |mapping| {
+            if mapping.start >= start && mapping.start < end && count < found.len() {
+                found[count] = Some(mapping);
+                count += 1;
+            }
+        });
+    });
+    found
+}
+
+#[test_case]
+fn translation_matches_the_mapper() {
+    let addr = VirtAddr::new(HEAP_START as u64 + 0x123);
+    with_kernel_memory(|kernel_memory| {
+        let phys_offset = kernel_memory.mapper.phys_offset();
+        let expected = kernel_memory.mapper.translate_addr(addr);
+        let translation = unsafe {
+            inspect::translate(kernel_memory.mapper.level_4_table(), phys_offset, addr)
+        };
+
+        assert_eq!(translation.phys(), expected);
+        assert!(translation.entries.iter().all(|entry| entry.is_some()));
+        let page = translation.page.unwrap();
+        assert_eq!(page.start.as_u64(), HEAP_START as u64);
+        assert_eq!(page.size, PAGE);
+        assert!(page.flags.contains(Flags::PRESENT | Flags::WRITABLE));
+        assert!(!page.flags.contains(Flags::USER_ACCESSIBLE));
+    });
+}
+
+#[test_case]
+fn heap_is_not_executable() {
+    assert!(protection::is_enabled());
+    let addr = VirtAddr::new(HEAP_START as u64);
+    let page = with_kernel_memory(|kernel_memory| unsafe {
+        let phys_offset = kernel_memory.mapper.phys_offset();
+        inspect::translate(kernel_memory.mapper.level_4_table(), phys_offset, addr).page
+    });
+    assert!(page.unwrap().flags.contains(Flags::NO_EXECUTE));
+}
+
+#[test_case]
+fn unmapped_addresses_have_no_translation() {
+    // the guard page in front of the heap
+    let addr = VirtAddr::new(HEAP_START as u64 - 1);
+    with_kernel_memory(|kernel_memory| {
+        let phys_offset = kernel_memory.mapper.phys_offset();
+        let translation = unsafe {
+            inspect::translate(kernel_memory.mapper.level_4_table(), phys_offset, addr)
+        };
+        assert_eq!(translation.page, None);
+        assert_eq!(translation.phys(), None);
+        // the walk stops at the first entry that is not present
+        let last = translation.entries.iter().flatten().last().unwrap();
+        assert!(!last.flags.contains(Flags::PRESENT));
+    });
+}
+
+#[test_case]
+fn contiguous_pages_are_coalesced() {
+    let region = VIRTUAL_REGIONS.lock()
+        .allocate(3 * PAGE, RegionKind::Mmio, "page table test")
+        .unwrap();
+    let page = |index: u64| Page::<Size4KiB>::containing_address(region.start + index * PAGE);
+    let frame = |index: u64| PhysFrame::containing_address(PhysAddr::new(VGA_FRAMES + index * PAGE));
+    with_kernel_memory(|kernel_memory| {
+        for index in 0..3 {
+            // the last page is read only, so it is a run of its own
+            let flags = if index < 2 { Flags::PRESENT | Flags::WRITABLE } else { Flags::PRESENT };
+            unsafe {
+                kernel_memory.mapper
+                    .map_to(page(index), frame(index), flags, &mut kernel_memory.frame_allocator)
+                    .unwrap()
+                    .flush();
+            }
+        }
+    });
+
+    let found = mappings_in(region.start, region.start + 3 * PAGE);
+    let writable = found[0].expect("the writable pages are missing");
+    assert_eq!(writable.start, region.start);
+    assert_eq!(writable.phys.as_u64(), VGA_FRAMES);
+    assert_eq!(writable.size, 2 * PAGE);
+    assert!(writable.flags.contains(Flags::WRITABLE));
+    assert!(writable.contains(region.start + PAGE + 8u64));
+    let read_only = found[1].expect("the read only page is missing");
+    assert_eq!(read_only.start, region.start + 2 * PAGE);
+    assert_eq!(read_only.size, PAGE);
+    assert!(!read_only.flags.contains(Flags::WRITABLE));
+    assert_eq!(found[2], None);
+
+    with_kernel_memory(|kernel_memory| {
+        for index in 0..3 {
+            // the VGA frames must not end up in the frame allocator, so no unmap_pages
+            kernel_memory.mapper.unmap(page(index)).unwrap().1.flush();
+        }
+    });
+    VIRTUAL_REGIONS.lock().release(region.start);
+}
+
+#[test_case]
+fn mappings_are_ordered_and_disjoint() {
+    let mut previous: Option<Mapping> = None;
+    let mut heap_found = false;
+    with_kernel_memory(|kernel_memory| unsafe {
+        let phys_offset = kernel_memory.mapper.phys_offset();
+        inspect::for_each_mapping(kernel_memory.mapper.level_4_table(), phys_offset, &mut |mapping| {
+            if let Some(previous) = previous {
+                assert!(previous.start.as_u64() + previous.size <= mapping.start.as_u64());
+            }
+            heap_found |= mapping.contains(VirtAddr::new(HEAP_START as u64));
+            previous = Some(mapping);
+        });
+    });
+    assert!(heap_found);
+}
+
+#[test_case]
+fn aligned_ranges_get_huge_pages() {
+    // room for a 2MiB aligned start somewhere in the region
+    let region = VIRTUAL_REGIONS.lock()
+        .allocate(3 * Size2MiB::SIZE, RegionKind::Other, "huge page test")
+        .unwrap();
+    let start = region.start.align_up(Size2MiB::SIZE);
+    let size = Size2MiB::SIZE + 2 * PAGE;
+    let translate = |addr: VirtAddr| with_kernel_memory(|kernel_memory| unsafe {
+        let phys_offset = kernel_memory.mapper.phys_offset();
+        inspect::translate(kernel_memory.mapper.level_4_table(), phys_offset, addr)
+    });
+    let map_and_unmap = || {
+        with_kernel_memory(|kernel_memory| {
+            kernel_memory.map_range(start, size, Flags::PRESENT | Flags::WRITABLE).unwrap()
+        });
+        let huge = translate(start + 8u64).page.unwrap();
+        assert_eq!(huge.size, Size2MiB::SIZE);
+        assert!(huge.flags.contains(Flags::HUGE_PAGE));
+        // the rest doesn't fill a huge page
+        let small = translate(start + Size2MiB::SIZE).page.unwrap();
+        assert_eq!(small.size, PAGE);
+        unsafe {
+            let ptr = (start + Size2MiB::SIZE - 8u64).as_mut_ptr::<u64>();
+            ptr.write_volatile(7);
+            assert_eq!(ptr.read_volatile(), 7);
+        }
+        with_kernel_memory(|kernel_memory| kernel_memory.unmap_range(start, size).unwrap());
+        assert_eq!(translate(start).page, None);
+    };
+    let free_frames = || with_kernel_memory(|kernel_memory| kernel_memory.frame_allocator.free_frames());
+
+    // the first round may leave page tables behind, the second must not take more frames
+    map_and_unmap();
+    let before = free_frames();
+    map_and_unmap();
+    assert_eq!(free_frames(), before);
+
+    VIRTUAL_REGIONS.lock().release(region.start);
+}
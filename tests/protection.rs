@@ -0,0 +1,136 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oubre_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{
+    bootinfo::{
+        MemoryMap,
+        MemoryRegionType,
+    },
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use oubre_os::{
+    allocator,
+    memory::{
+        self,
+        bitmap::BitmapFrameAllocator,
+        inspect::{self, Mapping},
+        protection,
+        KERNEL_MEMORY,
+    },
+};
+use x86_64::{
+    structures::paging::PageTableFlags as Flags,
+    VirtAddr,
+};
+
+lazy_static! {
+    // the test cases have no access to the boot info, so main stashes it here
+    static ref BOOT_INFO: Mutex<Option<&'static BootInfo>> = Mutex::new(None);
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    BOOT_INFO.lock().replace(boot_info);
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+    protection::protect_kernel(&boot_info.memory_map)
+        .expect("protecting the kernel failed");
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oubre_os::test_panic_handler(info);
+}
+
+fn memory_map() -> &'static MemoryMap {
+    let boot_info = BOOT_INFO.lock().expect("boot info not set");
+    &boot_info.memory_map
+}
+
+// calls 'f' for every mapping of the kernel image or stack frames, leaving out their
+// aliases in the mapping of all physical memory
+fn for_each_kernel_mapping(mut f: impl FnMut(Mapping, MemoryRegionType)) {
+    let mut kernel_memory = KERNEL_MEMORY.lock();
+    let mapper = &mut kernel_memory.as_mut().unwrap().mapper;
+    let phys_offset = mapper.phys_offset();
+    unsafe {
+        inspect::for_each_mapping(mapper.level_4_table(), phys_offset, &mut |mapping| {
+            if mapping.start == phys_offset + mapping.phys.as_u64() {
+                return;
+            }
+            let (start, end) = (mapping.phys.as_u64(), mapping.phys.as_u64() + mapping.size);
+            let region = memory_map().iter().find(|region| {
+                matches!(region.region_type, MemoryRegionType::Kernel | MemoryRegionType::KernelStack)
+                    && region.range.start_addr() < end
+                    && start < region.range.end_addr()
+            });
+            if let Some(region) = region {
+                f(mapping, region.region_type);
+            }
+        });
+    }
+}
+
+#[test_case]
+fn protection_is_enabled() {
+    assert!(protection::is_enabled());
+}
+
+#[test_case]
+fn no_kernel_page_is_writable_and_executable() {
+    let (mut code, mut stack) = (0, 0);
+    for_each_kernel_mapping(|mapping, region_type| {
+        let executable = !mapping.flags.contains(Flags::NO_EXECUTE);
+        assert!(
+            !(executable && mapping.flags.contains(Flags::WRITABLE)),
+            "{} is writable and executable",
+            mapping
+        );
+        match region_type {
+            MemoryRegionType::Kernel if executable => code += 1,
+            MemoryRegionType::KernelStack => {
+                assert!(!executable, "the kernel stack at {} is executable", mapping);
+                stack += 1;
+            }
+            _ => {}
+        }
+    });
+    // the walk found the code and the stack at all
+    assert!(code > 0 && stack > 0);
+}
+
+#[test_case]
+fn physical_memory_is_not_executable() {
+    let mut kernel_memory = KERNEL_MEMORY.lock();
+    let mapper = &mut kernel_memory.as_mut().unwrap().mapper;
+    let phys_offset = mapper.phys_offset();
+    let mut aliases = 0;
+    unsafe {
+        inspect::for_each_mapping(mapper.level_4_table(), phys_offset, &mut |mapping| {
+            if mapping.start == phys_offset + mapping.phys.as_u64() {
+                assert!(mapping.flags.contains(Flags::NO_EXECUTE), "{} is executable", mapping);
+                aliases += 1;
+            }
+        });
+    }
+    assert!(aliases > 0);
+}
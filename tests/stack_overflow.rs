@@ -3,6 +3,10 @@
 
 #![feature(abi_x86_interrupt)]
 
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
 use core::panic::PanicInfo;
 use lazy_static::lazy_static;
 use x86_64::structures::idt::{
@@ -12,10 +16,15 @@ use x86_64::structures::idt::{
 
 use oubre_os::{ 
     exit_qemu, 
+    memory::{
+        self,
+        bitmap::BitmapFrameAllocator,
+    },
     QemuExitCode, 
     serial_print, 
     serial_println
  };
+use x86_64::VirtAddr;
 
 lazy_static! {
     static ref TEST_IDT: InterruptDescriptorTable = {
@@ -42,10 +51,19 @@ pub fn init_test_idt() {
     TEST_IDT.load();
 }
 
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
     serial_print!("stack_overflow::stack_overflow...\t");
 
+    // the double fault stack is mapped when the GDT is loaded
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    memory::init_kernel_memory(mapper, frame_allocator);
+
     oubre_os::gdt::init();
     init_test_idt();
 
@@ -0,0 +1,194 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(oubre_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+
+use oubre_os::{
+    allocator::{self, HEAP_START},
+    interrupts,
+    memory::{
+        self,
+        address_space::{USER_END, USER_START},
+        bitmap::BitmapFrameAllocator,
+        vma::{RegionError, RegionKind, VirtualRegions, VIRTUAL_REGIONS},
+    },
+};
+use x86_64::{
+    structures::paging::{
+        Mapper,
+        Page,
+    },
+    VirtAddr,
+};
+
+const PAGE: u64 = 4096;
+const DYNAMIC_START: u64 = 0x_1000_0000;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+    // demand paging happens in the page fault handler
+    interrupts::init_idt();
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oubre_os::test_panic_handler(info);
+}
+
+fn addr(offset: u64) -> VirtAddr {
+    VirtAddr::new(DYNAMIC_START + offset)
+}
+
+#[test_case]
+fn heap_is_reserved() {
+    let heap = VIRTUAL_REGIONS.lock().find(VirtAddr::new(HEAP_START as u64))
+        .expect("the heap is not reserved");
+    assert_eq!(heap.kind, RegionKind::Heap);
+    assert_eq!(heap.start.as_u64(), HEAP_START as u64);
+}
+
+#[test_case]
+fn overlapping_regions_are_refused() {
+    let mut regions = VirtualRegions::new(DYNAMIC_START, DYNAMIC_START + 64 * PAGE);
+    let mmio = regions.reserve(addr(0), 2 * PAGE, RegionKind::Mmio, "mmio").unwrap();
+
+    assert_eq!(
+        regions.reserve(addr(PAGE), 2 * PAGE, RegionKind::Other, "other"),
+        Err(RegionError::Overlap(mmio))
+    );
+    assert_eq!(
+        regions.reserve(addr(PAGE / 2), PAGE, RegionKind::Other, "other"),
+        Err(RegionError::Unaligned)
+    );
+    // regions without guard pages may touch
+    assert!(regions.reserve(addr(2 * PAGE), PAGE, RegionKind::Other, "other").is_ok());
+}
+
+#[test_case]
+fn guard_pages_stay_free() {
+    let mut regions = VirtualRegions::new(DYNAMIC_START, DYNAMIC_START + 64 * PAGE);
+    let stack = regions.reserve(addr(4 * PAGE), 2 * PAGE, RegionKind::Stack, "stack").unwrap();
+
+    // the pages right in front of and behind the stack are its guards
+    assert_eq!(
+        regions.reserve(addr(3 * PAGE), PAGE, RegionKind::Mmio, "mmio"),
+        Err(RegionError::Overlap(stack))
+    );
+    assert_eq!(
+        regions.reserve(addr(6 * PAGE), PAGE, RegionKind::Mmio, "mmio"),
+        Err(RegionError::Overlap(stack))
+    );
+    assert!(regions.reserve(addr(7 * PAGE), PAGE, RegionKind::Mmio, "mmio").is_ok());
+}
+
+#[test_case]
+fn allocate_returns_lowest_free_range() {
+    let mut regions = VirtualRegions::new(DYNAMIC_START, DYNAMIC_START + 16 * PAGE);
+
+    let first = regions.allocate(2 * PAGE, RegionKind::Mmio, "first").unwrap();
+    assert_eq!(first.start, addr(0));
+    // a stack gets a guard page on both sides
+    let stack = regions.allocate(PAGE, RegionKind::Stack, "stack").unwrap();
+    assert_eq!(stack.start, addr(3 * PAGE));
+    let second = regions.allocate(PAGE, RegionKind::Mmio, "second").unwrap();
+    assert_eq!(second.start, addr(5 * PAGE));
+
+    // the freed range is handed out again
+    assert_eq!(regions.release(first.start), Some(first));
+    let third = regions.allocate(PAGE, RegionKind::Mmio, "third").unwrap();
+    assert_eq!(third.start, addr(0));
+
+    assert_eq!(
+        regions.allocate(16 * PAGE, RegionKind::Mmio, "too big"),
+        Err(RegionError::NoSpace)
+    );
+}
+
+#[test_case]
+fn regions_are_found_by_address() {
+    let mut regions = VirtualRegions::new(DYNAMIC_START, DYNAMIC_START + 16 * PAGE);
+    let region = regions.allocate(2 * PAGE, RegionKind::Other, "other").unwrap();
+
+    assert_eq!(regions.find(addr(PAGE + 8)), Some(region));
+    assert_eq!(regions.find(addr(2 * PAGE)), None);
+    assert_eq!(regions.iter().count(), 1);
+
+    regions.release(region.start);
+    assert_eq!(regions.find(addr(0)), None);
+    assert_eq!(regions.release(region.start), None);
+}
+
+#[test_case]
+fn user_regions_stay_in_user_space() {
+    assert_eq!(
+        VIRTUAL_REGIONS.lock().allocate(PAGE, RegionKind::User, "user"),
+        Err(RegionError::NotUserSpace)
+    );
+    assert_eq!(
+        VIRTUAL_REGIONS.lock().reserve(VirtAddr::new(USER_END - PAGE), 2 * PAGE, RegionKind::User, "user"),
+        Err(RegionError::NotUserSpace)
+    );
+
+    let mut regions = VirtualRegions::new(USER_START, USER_START + 16 * PAGE);
+    let region = regions.allocate(2 * PAGE, RegionKind::User, "user").unwrap();
+    assert_eq!(region.start, VirtAddr::new(USER_START));
+
+    // the kernel's page table doesn't back user regions on demand
+    let region = VIRTUAL_REGIONS.lock()
+        .reserve_demand_paged(VirtAddr::new(USER_START), PAGE, RegionKind::User, "user")
+        .unwrap();
+    assert!(!memory::map_on_demand(region.start));
+    VIRTUAL_REGIONS.lock().release(region.start);
+}
+
+#[test_case]
+fn demand_paged_pages_are_backed_on_first_touch() {
+    let region = VIRTUAL_REGIONS.lock()
+        .allocate_demand_paged(4 * PAGE, RegionKind::Other, "demand paged")
+        .unwrap();
+    let page = |index: u64| Page::containing_address(region.start + index * PAGE);
+    let is_mapped = |index: u64| {
+        let kernel_memory = memory::KERNEL_MEMORY.lock();
+        kernel_memory.as_ref().unwrap().mapper.translate_page(page(index)).is_ok()
+    };
+    assert!(!is_mapped(0));
+
+    let first = region.start.as_mut_ptr::<u64>();
+    let third = (region.start + 2 * PAGE + 8u64).as_mut_ptr::<u64>();
+    unsafe {
+        // fresh pages are zeroed
+        assert_eq!(first.read_volatile(), 0);
+        third.write_volatile(42);
+        assert_eq!(third.read_volatile(), 42);
+    }
+    assert!(is_mapped(0));
+    assert!(!is_mapped(1));
+    assert!(is_mapped(2));
+
+    let mut kernel_memory = memory::KERNEL_MEMORY.lock();
+    let kernel_memory = kernel_memory.as_mut().unwrap();
+    for index in [0, 2].iter() {
+        kernel_memory.unmap_pages(Page::range(page(*index), page(*index) + 1)).unwrap();
+    }
+    VIRTUAL_REGIONS.lock().release(region.start);
+}
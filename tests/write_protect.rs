@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+#![feature(abi_x86_interrupt)]
+
+use bootloader::{
+    entry_point,
+    BootInfo,
+};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{
+        InterruptDescriptorTable,
+        InterruptStackFrame,
+        PageFaultErrorCode,
+    },
+    VirtAddr,
+};
+
+use oubre_os::{
+    allocator,
+    exit_qemu,
+    memory::{
+        self,
+        bitmap::BitmapFrameAllocator,
+        protection,
+    },
+    QemuExitCode,
+    serial_print,
+    serial_println,
+};
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut test_idt = InterruptDescriptorTable::new();
+        test_idt.page_fault.set_handler_fn(test_page_fault_handler);
+        test_idt
+    };
+}
+
+// the code that is written to, it must not be inlined to have an address of its own
+#[inline(never)]
+fn target() -> u64 {
+    42
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    let expected = PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE;
+    if error_code.contains(expected) && Cr2::read() == VirtAddr::new(target as fn() -> u64 as usize as u64) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[Failed]\n");
+        serial_println!("unexpected page fault at {:?}: {:?}", Cr2::read(), error_code);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("write_protect::write_to_code...\t");
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+    protection::protect_kernel(&boot_info.memory_map)
+        .expect("protecting the kernel failed");
+    TEST_IDT.load();
+
+    // CR0.WP makes the read-only code fault for the kernel as well
+    assert_eq!(target(), 42);
+    unsafe { core::ptr::write_volatile(target as fn() -> u64 as usize as *mut u8, 0xc3) };
+
+    panic!("Execution continued after writing to code");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    oubre_os::test_panic_handler(info);
+}